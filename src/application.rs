@@ -1,130 +1,540 @@
+use std::{path::PathBuf, sync::Arc};
+
 use crate::plugins::{
-    rendering::{init_render_schedule, Camera},
+    actions::{ActionPlugin, InputMap},
+    animation::AnimationPlugin,
+    assets::AssetPlugin,
+    gamepad::{poll_input_backend, ActiveInputBackend, InputBackend},
+    input::{apply_input_event, finish_input_tick, InputEvent, InputPlugin},
+    pixel_perfect::PixelPerfectPlugin,
+    rendering::{
+        is_vsync, RenderOutput, RenderPlugin, RenderSettings, WgpuConfig, WindowPresentMode,
+    },
+    screenshot::ScreenshotPlugin,
     sprites::SpritePlugin,
-    Plugin,
+    transform::{store_previous_transforms, TransformPlugin},
+    App, PendingPlugin, Plugin, PluginId,
 };
+use crate::recording::{InputRecorder, InputRecording, RecordedFrame};
 use crate::timestep_scheduler::TimestepScheduler;
 use bevy_ecs::{
-    event::Event,
+    event::{Event, Events},
     schedule::{Schedule, ScheduleLabel},
     world::World,
 };
 
 use winit::{
+    dpi::PhysicalSize,
     event_loop::{self, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowBuilder},
 };
 
-use crate::{resources::Delta, timestep_scheduler::FixedUpdateScheduler};
+use crate::{
+    clock::{Clock, RealClock},
+    resources::{InterpolationAlpha, Time},
+    timestep_scheduler::{Scheduler, TimestepMode, DEFAULT_MAX_TICKS_PER_FRAME},
+};
 
 pub struct Application {
     world: World,
-    // window has to be after wgpu, because it has unsafe references onto the window
-    window: Window,
-    event_loop: EventLoop<()>,
+    scheduler: Scheduler,
+    // window has to be after wgpu, because it has unsafe references onto the window.
+    // the world shares it through RenderOutput, this one is dropped last
+    // both are None when running headless
+    window: Option<Arc<Window>>,
+    event_loop: Option<EventLoop<()>>,
+    recorder: Option<InputRecorder>,
+    plugins: Vec<PluginId>,
 }
 
 #[derive(Event)]
 pub struct ResizeEvent(pub winit::dpi::PhysicalSize<u32>);
 
+/// Send this event to stop the application after the current frame.
+#[derive(Event)]
+pub struct AppExit;
+
+/// Runs every update tick, see [`TimestepMode`].
 #[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct UpdateSchedule;
+pub struct UpdateSchedule;
 
+/// Runs once per frame in [`TimestepMode::Hybrid`], with the frame's delta.
+/// Input state is per tick, read it in the update ticks.
 #[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct RenderSchedule;
+pub struct FrameUpdateSchedule;
 
-impl Application {
-    pub async fn build() -> Result<Self, anyhow::Error> {
-        let event_loop = event_loop::EventLoop::new()?;
-        let window = WindowBuilder::new().build(&event_loop)?;
+/// Runs once per frame after the updates, ordered by [`RenderStage`](crate::plugins::rendering::RenderStage).
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct RenderSchedule;
 
-        let mut world = World::new();
+pub struct ApplicationBuilder {
+    input_backend: Option<Box<dyn InputBackend>>,
+    headless: Option<PhysicalSize<u32>>,
+    low_resolution: Option<PhysicalSize<u32>>,
+    record_input: Option<PathBuf>,
+    timestep: TimestepMode,
+    tick_rate: u16,
+    frame_rate: u16,
+    max_ticks_per_frame: u32,
+    clock: Option<Box<dyn Clock>>,
+    present_mode: wgpu::PresentMode,
+    render: Option<RenderPlugin>,
+    assets: AssetPlugin,
+    actions: ActionPlugin,
+    plugins: Vec<PendingPlugin>,
+}
+
+impl Default for ApplicationBuilder {
+    fn default() -> Self {
+        Self {
+            input_backend: None,
+            headless: None,
+            low_resolution: None,
+            record_input: None,
+            timestep: TimestepMode::default(),
+            tick_rate: 60,
+            frame_rate: 60,
+            max_ticks_per_frame: DEFAULT_MAX_TICKS_PER_FRAME,
+            clock: None,
+            present_mode: wgpu::PresentMode::Fifo,
+            render: Some(RenderPlugin::default()),
+            assets: AssetPlugin::default(),
+            actions: ActionPlugin::default(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+impl ApplicationBuilder {
+    /// Run without a window, rendering into an offscreen texture of the given size.
+    /// The adapter is requested without a compatible surface, so a software
+    /// fallback adapter is used when no hardware one is available.
+    pub fn headless(mut self, width: u32, height: u32) -> Self {
+        self.headless = Some(PhysicalSize::new(width, height));
+        self
+    }
+
+    /// Render all window cameras at a fixed resolution, integer upscaled to the
+    /// window. Meant for pixel art.
+    pub fn low_resolution(mut self, width: u32, height: u32) -> Self {
+        self.low_resolution = Some(PhysicalSize::new(width, height));
+        self
+    }
+
+    /// Directory assets are loaded from, defaults to `assets` in the working directory.
+    pub fn asset_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.assets.root = root.into();
+        self
+    }
 
-        let mut update_schedule = Schedule::new(UpdateSchedule);
-        world.add_schedule(update_schedule);
+    /// Where gamepad input comes from, there are no gamepads without one.
+    pub fn input_backend(mut self, backend: impl InputBackend) -> Self {
+        self.input_backend = Some(Box::new(backend));
+        self
+    }
 
-        let mut render_schedule = Schedule::new(RenderSchedule);
-        init_render_schedule(&mut world, &window, &mut render_schedule).await?;
+    /// Action bindings to start with, e.g. loaded from the player's config with
+    /// [`InputMap::load`]. Escape is only bound to exit in the default map.
+    pub fn input_map(mut self, input_map: InputMap) -> Self {
+        self.actions.input_map = input_map;
+        self
+    }
 
-        SpritePlugin {}.build(&mut world, &mut render_schedule);
+    /// Record all input to a file when the application exits, see [`InputRecording`].
+    pub fn record_input(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_input = Some(path.into());
+        self
+    }
 
-        world.add_schedule(render_schedule);
+    /// How updates are scheduled, fixed ticks by default.
+    pub fn timestep(mut self, mode: TimestepMode) -> Self {
+        self.timestep = mode;
+        self
+    }
 
-        world.spawn(Camera {
-            aspect: window.inner_size().width as f32 / window.inner_size().height as f32,
-            eye: (0.0, 0.0, 2.0).into(),
-            direction: glam::Vec3::NEG_Z,
-            fov: 90.0,
-            clip_near: 0.1,
-            clip_far: 100.0,
+    /// Update ticks per second, 60 by default, building fails for 0. Ignored by
+    /// [`TimestepMode::Variable`].
+    pub fn tick_rate(mut self, ticks_per_second: u16) -> Self {
+        self.tick_rate = ticks_per_second;
+        self
+    }
 
-            output: None,
-            view: None,
-            projection: glam::Mat4::IDENTITY,
-            uniform: None,
-            bind_group: None,
+    /// Frames rendered per second at most, 60 by default, building fails for 0.
+    /// Can be higher than the tick rate, moving entities need a
+    /// [`TransformInterpolation`](crate::plugins::transform::TransformInterpolation)
+    /// to show movement in the extra frames.
+    pub fn frame_rate(mut self, frames_per_second: u16) -> Self {
+        self.frame_rate = frames_per_second;
+        self
+    }
+
+    /// Update ticks a single frame runs at most. When updating falls behind
+    /// further, the simulation slows down instead of trying to catch up.
+    pub fn max_ticks_per_frame(mut self, max_ticks_per_frame: u32) -> Self {
+        self.max_ticks_per_frame = max_ticks_per_frame;
+        self
+    }
+
+    /// Where the scheduler gets the time from, the wall clock by default. A
+    /// [`ManualClock`](crate::clock::ManualClock) makes every run tick the same.
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// How frames are presented, Fifo by default. Change it later through the
+    /// [`WindowPresentMode`] resource. While presenting waits for vsync, the
+    /// frame rate is limited by the display instead of [`Self::frame_rate`].
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// How the gpu is set up, e.g. to require features or enable MSAA.
+    pub fn render_settings(mut self, settings: RenderSettings) -> Self {
+        self.render = Some(RenderPlugin { settings });
+        self
+    }
+
+    /// Leave out the [`RenderPlugin`] and everything drawing, for servers and
+    /// tests without a gpu. Usually combined with [`Self::headless`].
+    pub fn without_rendering(mut self) -> Self {
+        self.render = None;
+        self
+    }
+
+    /// Built together with the default plugins, after its dependencies. Replaces
+    /// the default plugin of the same type, e.g. a configured [`AssetPlugin`],
+    /// or one added before.
+    pub fn add_plugin<P: Plugin>(mut self, plugin: P) -> Self {
+        let plugin = PendingPlugin::new(plugin);
+        self.plugins.retain(|added| added.id() != plugin.id());
+        self.plugins.push(plugin);
+        self
+    }
+
+    pub async fn build(self) -> Result<Application, anyhow::Error> {
+        if self.timestep != TimestepMode::Variable && self.tick_rate == 0 {
+            anyhow::bail!("Tick rate has to be positive");
+        }
+
+        if self.frame_rate == 0 {
+            anyhow::bail!("Frame rate has to be positive");
+        }
+
+        let (window, event_loop, size) = match self.headless {
+            Some(size) => (None, None, size),
+            None => {
+                let event_loop = event_loop::EventLoop::new()?;
+                let window = Arc::new(WindowBuilder::new().build(&event_loop)?);
+                let size = window.inner_size();
+
+                (Some(window), Some(event_loop), size)
+            }
+        };
+
+        let mut world = World::new();
+        world.insert_resource(Events::<AppExit>::default());
+        world.insert_resource(Events::<ResizeEvent>::default());
+        world.init_resource::<Time>();
+        world.init_resource::<InterpolationAlpha>();
+
+        if let Some(backend) = self.input_backend {
+            world.insert_resource(ActiveInputBackend(backend));
+        }
+
+        world.add_schedule(Schedule::new(UpdateSchedule));
+        world.add_schedule(Schedule::new(FrameUpdateSchedule));
+        world.add_schedule(Schedule::new(RenderSchedule));
+
+        world.insert_resource(match &window {
+            Some(window) => RenderOutput::Window(window.clone()),
+            None => RenderOutput::Headless(size),
         });
 
-        Ok(Self {
+        world.insert_resource(WindowPresentMode(self.present_mode));
+
+        let rendering = self.render.is_some();
+        let mut plugins = Vec::new();
+
+        if let Some(render) = self.render {
+            plugins.push(PendingPlugin::new(render));
+        }
+
+        plugins.push(PendingPlugin::new(InputPlugin));
+        plugins.push(PendingPlugin::new(self.actions));
+        plugins.push(PendingPlugin::new(AnimationPlugin));
+        plugins.push(PendingPlugin::new(self.assets));
+        plugins.push(PendingPlugin::new(TransformPlugin));
+
+        if rendering {
+            if let Some(resolution) = self.low_resolution {
+                plugins.push(PendingPlugin::new(PixelPerfectPlugin {
+                    resolution: glam::UVec2::new(resolution.width, resolution.height),
+                }));
+            }
+
+            plugins.push(PendingPlugin::new(SpritePlugin));
+            plugins.push(PendingPlugin::new(ScreenshotPlugin));
+        }
+
+        // added plugins replace the defaults in place, so they keep their order
+        for plugin in self.plugins {
+            match plugins
+                .iter()
+                .position(|default| default.id() == plugin.id())
+            {
+                Some(index) => plugins[index] = plugin,
+                None => plugins.push(plugin),
+            }
+        }
+
+        let mut built = Vec::new();
+        App::new(&mut world, &mut built).build_plugins(plugins)?;
+
+        Ok(Application {
             world,
+            scheduler: Scheduler::new(
+                self.timestep,
+                self.tick_rate,
+                self.frame_rate,
+                self.max_ticks_per_frame,
+                self.clock.unwrap_or_else(|| Box::new(RealClock::new())),
+            ),
             window,
             event_loop,
+            recorder: self.record_input.map(InputRecorder::new),
+            plugins: built,
         })
     }
+}
+
+impl Application {
+    pub fn builder() -> ApplicationBuilder {
+        ApplicationBuilder::default()
+    }
+
+    pub async fn build() -> Result<Self, anyhow::Error> {
+        Self::builder().build().await
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Builds a plugin into the running application, its dependencies have to
+    /// be built already. Plugins that are built already cant be added again.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> Result<(), anyhow::Error> {
+        App::new(&mut self.world, &mut self.plugins).build_plugins(vec![PendingPlugin::new(plugin)])
+    }
+
+    /// Applies input as if it came from the window, before the next update tick.
+    pub fn send_input(&mut self, event: InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.scheduler.ticks(), &event);
+        }
+
+        apply_input_event(&mut self.world, event);
+    }
+
+    /// Runs all due update ticks and renders a single frame.
+    pub fn update(&mut self) {
+        for event in poll_input_backend(&mut self.world) {
+            self.send_input(InputEvent::Gamepad(event));
+        }
+
+        let (time_scale, paused) = self.apply_time_scale();
+
+        let mut deltas = Vec::new();
+        self.scheduler.update(|delta| {
+            deltas.push(delta);
+            Self::run_tick(&mut self.world, delta);
+        });
+
+        let mut frame_delta = None;
+        self.scheduler.frame_update(|delta| {
+            frame_delta = Some(delta);
+            Self::run_frame_update(&mut self.world, delta);
+        });
+
+        let mut frame_alpha = 0.0;
+        self.scheduler.render(|alpha| {
+            frame_alpha = alpha;
+            Self::run_render(&mut self.world, alpha);
+        });
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.finish_frame(RecordedFrame {
+                time_scale,
+                paused,
+                deltas,
+                frame_delta,
+                alpha: frame_alpha,
+            });
+        }
+
+        // presenting already waits for the display, sleeping as well would
+        // miss vblanks
+        let vsync = self.window.is_some()
+            && self
+                .world
+                .get_resource::<WgpuConfig>()
+                .is_some_and(|config| is_vsync(config.0.present_mode));
+        self.scheduler.limit_frame_rate(!vsync);
+    }
+
+    /// Applies [`Time`]'s scale and pause for the next frame, and returns them.
+    fn apply_time_scale(&mut self) -> (f32, bool) {
+        let time = self.world.resource::<Time>();
+        let (scale, paused) = (time.scale(), time.is_paused());
+        self.scheduler.set_time_scale(scale, paused);
+
+        (scale, paused)
+    }
+
+    fn run_tick(world: &mut World, delta: f32) {
+        store_previous_transforms(world);
+        world.resource_mut::<Time>().advance_tick(delta);
+        world.run_schedule(UpdateSchedule);
+        finish_input_tick(world);
+    }
+
+    fn run_frame_update(world: &mut World, delta: f32) {
+        world.resource_mut::<Time>().set_frame_delta(delta);
+        world.run_schedule(FrameUpdateSchedule);
+    }
+
+    fn run_render(world: &mut World, alpha: f32) {
+        world.insert_resource(InterpolationAlpha(alpha));
+        world.run_schedule(RenderSchedule);
+    }
+
+    /// Runs the recorded frames as fast as possible, applying each event before
+    /// the frame it arrived before. Each frame runs the same schedules as
+    /// [`Application::update`], with the recorded deltas and interpolation
+    /// alpha instead of the clock's, so it works for every [`TimestepMode`].
+    ///
+    /// Replaying the same recording on a freshly built application ends in the
+    /// same state every time, and in the state the recorded run ended in.
+    pub fn replay(&mut self, recording: &InputRecording) {
+        log::info!(
+            "Replaying {} frames, {} ticks of input",
+            recording.frames.len(),
+            recording.ticks
+        );
+
+        let mut events = recording.events.iter().map(|(_, event)| event);
+        let mut sent = 0;
+
+        for (frame_events, frame) in &recording.frames {
+            for event in events.by_ref().take(frame_events - sent) {
+                self.send_input(event.clone());
+            }
+            sent = *frame_events;
+
+            // keeps the scheduler in step in case the application runs on
+            self.scheduler
+                .set_time_scale(frame.time_scale, frame.paused);
+
+            for &delta in &frame.deltas {
+                self.scheduler.count_tick();
+                Self::run_tick(&mut self.world, delta);
+            }
+
+            if let Some(delta) = frame.frame_delta {
+                Self::run_frame_update(&mut self.world, delta);
+            }
+
+            Self::run_render(&mut self.world, frame.alpha);
+
+            if let Some(recorder) = &mut self.recorder {
+                recorder.finish_frame(frame.clone());
+            }
+        }
+
+        // input after the last frame still changes the state, e.g. cursor position
+        for event in events {
+            self.send_input(event.clone());
+        }
+    }
+
+    /// Renders a single frame without running any updates.
+    pub fn render(&mut self) {
+        self.world.run_schedule(RenderSchedule);
+    }
+
+    fn exit_requested(&mut self) -> bool {
+        let mut exit = self.world.resource_mut::<Events<AppExit>>();
+        let requested = !exit.is_empty();
+        exit.clear();
+
+        requested
+    }
 
     pub fn run(mut self) {
         log::info!("Starting application");
 
+        let Some(event_loop) = self.event_loop.take() else {
+            return self.run_headless();
+        };
+
         use winit::event::Event;
         use winit::event::WindowEvent;
 
-        self.window.set_title("Made with Unity(TM)");
-
-        let mut scheduler = FixedUpdateScheduler::new(60, 60);
+        let window_id = self.window().id();
+        self.window().set_title("Made with Unity(TM)");
 
-        self.event_loop
-            .run(move |event, window| match event {
+        event_loop
+            .run(move |event, event_loop| match event {
                 Event::AboutToWait => {
-                    scheduler.after_frame();
-                    self.window.request_redraw();
+                    self.scheduler.after_frame();
+                    self.window().request_redraw();
                 }
                 Event::WindowEvent {
-                    window_id,
+                    window_id: event_window_id,
                     event: window_event,
-                } if window_id == self.window.id() => match window_event {
-                    WindowEvent::RedrawRequested => {
-                        scheduler.update(|delta| {
-                            self.world.insert_resource(Delta(delta));
-                            self.world.run_schedule(UpdateSchedule);
-                        });
-
-                        self.world.remove_resource::<Delta>();
-
-                        scheduler.render(|| {
-                            self.world.run_schedule(RenderSchedule);
-                        });
+                } if event_window_id == window_id => {
+                    for input in InputEvent::from_window_event(&window_event) {
+                        self.send_input(input);
                     }
-                    WindowEvent::CloseRequested => {
-                        window.exit();
-                    }
-                    WindowEvent::KeyboardInput {
-                        device_id: _,
-                        event,
-                        is_synthetic: _,
-                    } => {
-                        if event.physical_key == PhysicalKey::Code(KeyCode::Escape) {
-                            window.exit();
+
+                    match window_event {
+                        WindowEvent::RedrawRequested => {
+                            self.update();
+
+                            if self.exit_requested() {
+                                event_loop.exit();
+                            }
                         }
+                        WindowEvent::CloseRequested => {
+                            event_loop.exit();
+                        }
+                        // TODO: maybe handle scale factor changed event
+                        WindowEvent::Resized(size) => {
+                            self.world.send_event(ResizeEvent(size));
+                        }
+                        _ => (),
                     }
-                    // TODO: maybe handle scale factor changed event
-                    WindowEvent::Resized(size) => {
-                        self.world.send_event(ResizeEvent(size));
-                    }
-                    _ => (),
-                },
+                }
                 _ => (),
             })
             .expect("Event loop failed");
     }
+
+    fn window(&self) -> &Window {
+        self.window.as_deref().expect("application has a window")
+    }
+
+    fn run_headless(mut self) {
+        log::info!("Running headless");
+
+        while !self.exit_requested() {
+            self.update();
+            self.scheduler.after_frame();
+        }
+    }
 }
@@ -0,0 +1,96 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Source of time for the [`TimestepScheduler`](crate::timestep_scheduler::TimestepScheduler)s.
+pub trait Clock: Send + 'static {
+    /// Time since the clock was created, never decreases.
+    fn now(&self) -> Duration;
+    /// Waits until `now` advanced by `duration`.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall clock time.
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Only advances when told to, for deterministic runs. Sleeping advances it
+/// instantly. Clones share their time, keep one to advance it.
+#[derive(Clone, Default)]
+pub struct ManualClock(Arc<Mutex<Duration>>);
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Runs `inner` faster or slower, including frame pacing. To only slow down
+/// the simulation, use [`Time::set_scale`](crate::resources::Time::set_scale).
+pub struct ScaledClock<C: Clock> {
+    inner: C,
+    scale: f32,
+}
+
+impl<C: Clock> ScaledClock<C> {
+    /// `scale` has to be positive, 2 runs twice as fast.
+    pub fn new(inner: C, scale: f32) -> Self {
+        assert!(scale > 0.0, "clock scale has to be positive, got {scale}");
+
+        Self { inner, scale }
+    }
+}
+
+impl<C: Clock> Clock for ScaledClock<C> {
+    #[inline]
+    fn now(&self) -> Duration {
+        self.inner.now().mul_f32(self.scale)
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) {
+        self.inner.sleep(duration.div_f32(self.scale));
+    }
+}
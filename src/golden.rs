@@ -0,0 +1,199 @@
+use std::{fmt, path::PathBuf};
+
+use bevy_ecs::{entity::Entity, world::World};
+use pollster::FutureExt;
+
+use crate::{
+    application::Application,
+    plugins::{
+        assets::AssetServer,
+        rendering::{Camera, RenderTarget},
+        screenshot::capture_camera,
+    },
+};
+
+/// Set to re-record the reference images instead of comparing against them.
+pub const BLESS_ENV: &str = "MUSH_BLESS";
+
+/// Renders a scene headless and compares the window camera's frame against a
+/// reference png. Missing references fail the test, set [`BLESS_ENV`] to
+/// record them.
+pub struct GoldenTest {
+    name: String,
+    width: u32,
+    height: u32,
+    frames: usize,
+    tolerance: u8,
+    reference_dir: PathBuf,
+}
+
+impl GoldenTest {
+    pub fn new(name: impl Into<String>) -> Self {
+        let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or(".".into());
+
+        Self {
+            name: name.into(),
+            width: 256,
+            height: 256,
+            frames: 1,
+            tolerance: 2,
+            reference_dir: PathBuf::from(manifest_dir).join("tests").join("golden"),
+        }
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// How often the render schedule runs before capturing.
+    pub fn frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// Largest per channel difference that still counts as equal.
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn reference_dir(mut self, reference_dir: impl Into<PathBuf>) -> Self {
+        self.reference_dir = reference_dir.into();
+        self
+    }
+
+    pub fn run(self, setup: impl FnOnce(&mut World)) -> Result<(), anyhow::Error> {
+        let mut app = Application::builder()
+            .headless(self.width, self.height)
+            .build()
+            .block_on()?;
+
+        setup(app.world_mut());
+
+        // assets are moved into the world while rendering
+        while app.world().resource::<AssetServer>().is_loading() {
+            app.render();
+        }
+
+        for _ in 0..self.frames {
+            app.render();
+        }
+
+        let camera = app
+            .world_mut()
+            .query::<(Entity, &Camera)>()
+            .iter(app.world())
+            .filter(|(_, camera)| camera.target == RenderTarget::Window)
+            .max_by_key(|(_, camera)| camera.order)
+            .map(|(entity, _)| entity)
+            .ok_or(anyhow::anyhow!("No camera renders to the window"))?;
+
+        let actual = capture_camera(app.world(), camera)?;
+        let reference_path = self.reference_dir.join(format!("{}.png", self.name));
+
+        if std::env::var_os(BLESS_ENV).is_some() {
+            std::fs::create_dir_all(&self.reference_dir)?;
+            actual.save(&reference_path)?;
+            log::warn!("Recorded reference image {}", reference_path.display());
+
+            return Ok(());
+        }
+
+        if !reference_path.exists() {
+            anyhow::bail!(
+                "{}: missing reference image {}, run with {BLESS_ENV}=1 to record it",
+                self.name,
+                reference_path.display()
+            );
+        }
+
+        let expected = image::open(&reference_path)?.to_rgba8();
+
+        if let Err(mismatch) = compare_images(&actual, &expected, self.tolerance) {
+            let actual_path = self.reference_dir.join(format!("{}.actual.png", self.name));
+            actual.save(&actual_path)?;
+
+            anyhow::bail!(
+                "{}: {mismatch}, actual output written to {}",
+                self.name,
+                actual_path.display()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageMismatch {
+    Size {
+        actual: (u32, u32),
+        expected: (u32, u32),
+    },
+    Pixels {
+        differing: usize,
+        max_difference: u8,
+    },
+}
+
+impl fmt::Display for ImageMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size { actual, expected } => write!(
+                f,
+                "image is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Self::Pixels {
+                differing,
+                max_difference,
+            } => write!(
+                f,
+                "{differing} pixels differ, by up to {max_difference} per channel"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageMismatch {}
+
+/// Compares two images channel by channel, allowing differences up to `tolerance`.
+pub fn compare_images(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+    tolerance: u8,
+) -> Result<(), ImageMismatch> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(ImageMismatch::Size {
+            actual: actual.dimensions(),
+            expected: expected.dimensions(),
+        });
+    }
+
+    let mut differing = 0;
+    let mut max_difference = 0;
+
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let difference =
+            a.0.iter()
+                .zip(e.0.iter())
+                .map(|(a, e)| a.abs_diff(*e))
+                .max()
+                .unwrap_or(0);
+
+        if difference > tolerance {
+            differing += 1;
+            max_difference = max_difference.max(difference);
+        }
+    }
+
+    match differing {
+        0 => Ok(()),
+        differing => Err(ImageMismatch::Pixels {
+            differing,
+            max_difference,
+        }),
+    }
+}
@@ -1,5 +1,8 @@
 pub mod application;
-mod resources;
-mod timestep_scheduler;
-mod plugins;
+pub mod clock;
+pub mod golden;
+pub mod recording;
+pub mod resources;
+pub mod timestep_scheduler;
+pub mod plugins;
 
@@ -1,4 +1,10 @@
-use mush::application::Application;
+use mush::{
+    application::Application,
+    plugins::{
+        assets::AssetServer, images::Image, rendering::Camera, sprites::Sprite,
+        transform::Transform,
+    },
+};
 use pollster::FutureExt;
 
 fn main() {
@@ -6,5 +12,22 @@ fn main() {
         .filter_level(log::LevelFilter::Info)
         .init();
 
-    Application::build().block_on().expect("init failed").run();
+    let mut app = Application::build().block_on().expect("init failed");
+    let world = app.world_mut();
+
+    world.spawn(Camera::default());
+
+    let happy_tree = world
+        .resource::<AssetServer>()
+        .load::<Image>("happy-tree.png");
+
+    world.spawn((
+        Sprite {
+            size: glam::Vec2::splat(0.5),
+            ..Sprite::new(happy_tree)
+        },
+        Transform::IDENTITY,
+    ));
+
+    app.run();
 }
@@ -0,0 +1,577 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    path::Path,
+};
+
+use bevy_ecs::{
+    event::EventWriter,
+    schedule::IntoSystemConfigs as _,
+    system::{Res, ResMut, Resource},
+};
+use winit::keyboard::KeyCode;
+
+use crate::application::{AppExit, UpdateSchedule};
+
+use super::{
+    gamepad::{Axis, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType},
+    input::{Input, InputPlugin, InputSet, MouseButton},
+    App, Plugin, PluginId,
+};
+
+/// Action that stops the application, bound to Escape by default.
+pub const EXIT: &str = "Exit";
+
+/// Something that drives an action. Buttons have a value of 0 or 1, axes
+/// anything from -1 to 1.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// The button on any gamepad
+    GamepadButton(GamepadButtonType),
+    /// The axis on any gamepad, values within `dead_zone` of zero count as
+    /// zero and the rest is rescaled to still reach 1
+    GamepadAxis {
+        axis: GamepadAxisType,
+        dead_zone: f32,
+    },
+    /// -1 while `negative` is held, 1 while `positive` is, 0 for both
+    KeyAxis {
+        negative: KeyCode,
+        positive: KeyCode,
+    },
+    /// Active while all of its bindings are, with the value of the last one
+    Chord(Vec<Binding>),
+}
+
+impl Binding {
+    fn value(&self, inputs: &Inputs) -> f32 {
+        let button = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+
+        match self {
+            Binding::Key(key) => button(held(inputs.keys, *key)),
+            Binding::Mouse(mouse_button) => button(held(inputs.mouse_buttons, *mouse_button)),
+            Binding::GamepadButton(gamepad_button) => button(
+                inputs
+                    .gamepad_buttons
+                    .get_pressed()
+                    .chain(inputs.gamepad_buttons.get_just_pressed())
+                    .any(|pressed| pressed.button == *gamepad_button),
+            ),
+            Binding::GamepadAxis { axis, dead_zone } => inputs
+                .gamepad_axes
+                .iter()
+                .filter(|(gamepad_axis, _)| gamepad_axis.axis == *axis)
+                .map(|(_, &value)| apply_dead_zone(value, *dead_zone))
+                .fold(0.0, |strongest: f32, value| {
+                    if value.abs() > strongest.abs() {
+                        value
+                    } else {
+                        strongest
+                    }
+                }),
+            Binding::KeyAxis { negative, positive } => {
+                button(held(inputs.keys, *positive)) - button(held(inputs.keys, *negative))
+            }
+            Binding::Chord(bindings) => bindings
+                .iter()
+                .map(|binding| binding.value(inputs))
+                .try_fold(0.0, |_, value| (value != 0.0).then_some(value))
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+/// Taps released before the tick ran count as held for that tick, so the
+/// action is still just pressed once.
+fn held<T: Copy + Eq + Hash + Send + Sync + 'static>(input: &Input<T>, button: T) -> bool {
+    input.pressed(button) || input.just_pressed(button)
+}
+
+fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value.abs() <= dead_zone {
+        return 0.0;
+    }
+
+    value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone).max(f32::EPSILON)
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "key {key:?}"),
+            Binding::Mouse(MouseButton::Other(button)) => write!(f, "mouse {button}"),
+            Binding::Mouse(button) => write!(f, "mouse {button:?}"),
+            Binding::GamepadButton(button) => write!(f, "pad {button:?}"),
+            Binding::GamepadAxis { axis, dead_zone } => write!(f, "axis {axis:?} {dead_zone}"),
+            Binding::KeyAxis { negative, positive } => write!(f, "keys {negative:?} {positive:?}"),
+            Binding::Chord(bindings) => {
+                write!(f, "chord ")?;
+
+                for (index, binding) in bindings.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " + ")?;
+                    }
+                    write!(f, "{binding}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let source = source.trim();
+
+        if let Some(chord) = source.strip_prefix("chord ") {
+            return Ok(Binding::Chord(
+                chord.split('+').map(str::parse).collect::<Result<_, _>>()?,
+            ));
+        }
+
+        let words: Vec<_> = source.split_whitespace().collect();
+
+        let binding = match words.as_slice() {
+            ["key", key] => Binding::Key(parse_key(key)?),
+            ["mouse", button] => Binding::Mouse(parse_mouse_button(button)?),
+            ["pad", button] => {
+                Binding::GamepadButton(parse_named(button, &GamepadButtonType::ALL)?)
+            }
+            ["axis", axis, dead_zone] => Binding::GamepadAxis {
+                axis: parse_named(axis, &GamepadAxisType::ALL)?,
+                dead_zone: dead_zone.parse()?,
+            },
+            ["axis", axis] => Binding::GamepadAxis {
+                axis: parse_named(axis, &GamepadAxisType::ALL)?,
+                dead_zone: DEFAULT_DEAD_ZONE,
+            },
+            ["keys", negative, positive] => Binding::KeyAxis {
+                negative: parse_key(negative)?,
+                positive: parse_key(positive)?,
+            },
+            _ => anyhow::bail!("Unknown binding {source}"),
+        };
+
+        Ok(binding)
+    }
+}
+
+/// Dead zone of axis bindings in config files that do not specify one.
+pub const DEFAULT_DEAD_ZONE: f32 = 0.15;
+
+pub(crate) fn parse_named<T: Copy + fmt::Debug>(name: &str, all: &[T]) -> Result<T, anyhow::Error> {
+    all.iter()
+        .copied()
+        .find(|value| format!("{value:?}") == name)
+        .ok_or(anyhow::anyhow!("Unknown name {name}"))
+}
+
+pub(crate) fn parse_key(name: &str) -> Result<KeyCode, anyhow::Error> {
+    parse_named(name, &KEY_CODES).map_err(|_| anyhow::anyhow!("Unknown key {name}"))
+}
+
+pub(crate) fn parse_mouse_button(name: &str) -> Result<MouseButton, anyhow::Error> {
+    let button = match name {
+        "Left" => MouseButton::Left,
+        "Right" => MouseButton::Right,
+        "Middle" => MouseButton::Middle,
+        "Back" => MouseButton::Back,
+        "Forward" => MouseButton::Forward,
+        other => MouseButton::Other(
+            other
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Unknown mouse button {other}"))?,
+        ),
+    };
+
+    Ok(button)
+}
+
+/// Bindings of every action, can be changed at runtime.
+///
+/// Saved as one line per action, with its bindings separated by commas:
+///
+/// ```text
+/// Exit: key Escape
+/// Jump: key Space, mouse Left, pad South
+/// MoveX: keys KeyA KeyD, axis LeftStickX 0.2
+/// Save: chord key ControlLeft + key KeyS
+/// ```
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct InputMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+}
+
+impl InputMap {
+    pub fn with_binding(mut self, action: impl Into<String>, binding: Binding) -> Self {
+        self.bind(action, binding);
+        self
+    }
+
+    /// Adds a binding, actions can have any number of them.
+    pub fn bind(&mut self, action: impl Into<String>, binding: Binding) {
+        let bindings = self.actions.entry(action.into()).or_default();
+
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces all bindings of `action` with `binding`.
+    pub fn rebind(&mut self, action: impl Into<String>, binding: Binding) {
+        self.actions.insert(action.into(), vec![binding]);
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: &Binding) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.retain(|bound| bound != binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(String::as_str)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+
+        source
+            .parse()
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for InputMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (action, bindings) in &self.actions {
+            write!(f, "{action}:")?;
+
+            for (index, binding) in bindings.iter().enumerate() {
+                let separator = if index == 0 { " " } else { ", " };
+                write!(f, "{separator}{binding}")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for InputMap {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (action, source) = line.split_once(':').ok_or(anyhow::anyhow!(
+                "line {}: expected action: bindings",
+                number + 1
+            ))?;
+
+            // actions without bindings stay listed, so they can be bound later
+            let bindings = map.actions.entry(action.trim().to_owned()).or_default();
+
+            for binding in source.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                let binding = binding
+                    .parse()
+                    .map_err(|error| anyhow::anyhow!("line {}: {error}", number + 1))?;
+
+                bindings.push(binding);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// Values of all actions, updated once per update tick from the [`InputMap`].
+/// Order systems reading it after [`InputSet`]. A button tapped between two
+/// ticks is pressed for one tick.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    values: HashMap<String, f32>,
+    previous: HashMap<String, f32>,
+}
+
+impl ActionState {
+    /// Strongest value among the action's bindings, 0 for unknown actions.
+    pub fn value(&self, action: &str) -> f32 {
+        self.values.get(action).copied().unwrap_or(0.0)
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.value(action) != 0.0
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.pressed(action) && self.previous.get(action).copied().unwrap_or(0.0) == 0.0
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        !self.pressed(action) && self.previous.get(action).copied().unwrap_or(0.0) != 0.0
+    }
+}
+
+struct Inputs<'a> {
+    keys: &'a Input<KeyCode>,
+    mouse_buttons: &'a Input<MouseButton>,
+    gamepad_buttons: &'a Input<GamepadButton>,
+    gamepad_axes: &'a Axis<GamepadAxis>,
+}
+
+pub fn update_action_state_system(
+    input_map: Res<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut action_state: ResMut<ActionState>,
+) {
+    let inputs = Inputs {
+        keys: &keys,
+        mouse_buttons: &mouse_buttons,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+
+    let action_state = &mut *action_state;
+    std::mem::swap(&mut action_state.values, &mut action_state.previous);
+    action_state.values.clear();
+
+    for (action, bindings) in &input_map.actions {
+        let value = bindings.iter().map(|binding| binding.value(&inputs)).fold(
+            0.0,
+            |strongest: f32, value| {
+                if value.abs() > strongest.abs() {
+                    value
+                } else {
+                    strongest
+                }
+            },
+        );
+
+        action_state.values.insert(action.clone(), value);
+    }
+}
+
+fn exit_on_action_system(action_state: Res<ActionState>, mut exit: EventWriter<AppExit>) {
+    if action_state.just_pressed(EXIT) {
+        exit.send(AppExit);
+    }
+}
+
+/// Named actions on top of the raw input. Starts with `input_map`, which
+/// defaults to binding [`EXIT`] to Escape.
+pub struct ActionPlugin {
+    pub input_map: InputMap,
+}
+
+impl Default for ActionPlugin {
+    fn default() -> Self {
+        Self {
+            input_map: InputMap::default().with_binding(EXIT, Binding::Key(KeyCode::Escape)),
+        }
+    }
+}
+
+impl Plugin for ActionPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.insert_resource(self.input_map);
+        world.init_resource::<ActionState>();
+
+        app.add_systems(
+            UpdateSchedule,
+            (
+                update_action_state_system.in_set(InputSet),
+                exit_on_action_system.after(InputSet),
+            ),
+        );
+
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![PluginId::of::<InputPlugin>()]
+    }
+}
+
+/// Every key, to parse their names in config files.
+#[rustfmt::skip]
+const KEY_CODES: [KeyCode; 194] = [
+    KeyCode::Backquote, KeyCode::Backslash, KeyCode::BracketLeft, KeyCode::BracketRight,
+    KeyCode::Comma, KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8,
+    KeyCode::Digit9, KeyCode::Equal, KeyCode::IntlBackslash, KeyCode::IntlRo, KeyCode::IntlYen,
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF,
+    KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL,
+    KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ, KeyCode::Minus, KeyCode::Period, KeyCode::Quote, KeyCode::Semicolon,
+    KeyCode::Slash, KeyCode::AltLeft, KeyCode::AltRight, KeyCode::Backspace, KeyCode::CapsLock,
+    KeyCode::ContextMenu, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::Enter,
+    KeyCode::SuperLeft, KeyCode::SuperRight, KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::Space,
+    KeyCode::Tab, KeyCode::Convert, KeyCode::KanaMode, KeyCode::Lang1, KeyCode::Lang2,
+    KeyCode::Lang3, KeyCode::Lang4, KeyCode::Lang5, KeyCode::NonConvert, KeyCode::Delete,
+    KeyCode::End, KeyCode::Help, KeyCode::Home, KeyCode::Insert, KeyCode::PageDown, KeyCode::PageUp,
+    KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::NumLock,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadAdd, KeyCode::NumpadBackspace, KeyCode::NumpadClear, KeyCode::NumpadClearEntry,
+    KeyCode::NumpadComma, KeyCode::NumpadDecimal, KeyCode::NumpadDivide, KeyCode::NumpadEnter,
+    KeyCode::NumpadEqual, KeyCode::NumpadHash, KeyCode::NumpadMemoryAdd, KeyCode::NumpadMemoryClear,
+    KeyCode::NumpadMemoryRecall, KeyCode::NumpadMemoryStore, KeyCode::NumpadMemorySubtract,
+    KeyCode::NumpadMultiply, KeyCode::NumpadParenLeft, KeyCode::NumpadParenRight, KeyCode::NumpadStar,
+    KeyCode::NumpadSubtract, KeyCode::Escape, KeyCode::Fn, KeyCode::FnLock, KeyCode::PrintScreen,
+    KeyCode::ScrollLock, KeyCode::Pause, KeyCode::BrowserBack, KeyCode::BrowserFavorites,
+    KeyCode::BrowserForward, KeyCode::BrowserHome, KeyCode::BrowserRefresh, KeyCode::BrowserSearch,
+    KeyCode::BrowserStop, KeyCode::Eject, KeyCode::LaunchApp1, KeyCode::LaunchApp2, KeyCode::LaunchMail,
+    KeyCode::MediaPlayPause, KeyCode::MediaSelect, KeyCode::MediaStop, KeyCode::MediaTrackNext,
+    KeyCode::MediaTrackPrevious, KeyCode::Power, KeyCode::Sleep, KeyCode::AudioVolumeDown,
+    KeyCode::AudioVolumeMute, KeyCode::AudioVolumeUp, KeyCode::WakeUp, KeyCode::Meta, KeyCode::Hyper,
+    KeyCode::Turbo, KeyCode::Abort, KeyCode::Resume, KeyCode::Suspend, KeyCode::Again,
+    KeyCode::Copy, KeyCode::Cut, KeyCode::Find, KeyCode::Open, KeyCode::Paste, KeyCode::Props,
+    KeyCode::Select, KeyCode::Undo, KeyCode::Hiragana, KeyCode::Katakana, KeyCode::F1,
+    KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6, KeyCode::F7, KeyCode::F8,
+    KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12, KeyCode::F13, KeyCode::F14,
+    KeyCode::F15, KeyCode::F16, KeyCode::F17, KeyCode::F18, KeyCode::F19, KeyCode::F20,
+    KeyCode::F21, KeyCode::F22, KeyCode::F23, KeyCode::F24, KeyCode::F25, KeyCode::F26,
+    KeyCode::F27, KeyCode::F28, KeyCode::F29, KeyCode::F30, KeyCode::F31, KeyCode::F32,
+    KeyCode::F33, KeyCode::F34, KeyCode::F35,
+];
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{schedule::Schedule, world::World};
+
+    use super::*;
+
+    fn world_with_actions() -> (World, Schedule) {
+        let mut world = World::new();
+        world.init_resource::<Input<KeyCode>>();
+        world.init_resource::<Input<MouseButton>>();
+        world.init_resource::<Input<GamepadButton>>();
+        world.init_resource::<Axis<GamepadAxis>>();
+        world.init_resource::<ActionState>();
+        world
+            .insert_resource(InputMap::default().with_binding(EXIT, Binding::Key(KeyCode::Escape)));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_action_state_system);
+
+        (world, schedule)
+    }
+
+    #[test]
+    fn tap_between_ticks_is_pressed_for_one_tick() {
+        let (mut world, mut schedule) = world_with_actions();
+
+        let mut keys = world.resource_mut::<Input<KeyCode>>();
+        keys.press(KeyCode::Escape);
+        keys.release(KeyCode::Escape);
+
+        schedule.run(&mut world);
+        assert!(world.resource::<ActionState>().just_pressed(EXIT));
+
+        world.resource_mut::<Input<KeyCode>>().clear();
+        schedule.run(&mut world);
+
+        let actions = world.resource::<ActionState>();
+        assert!(!actions.pressed(EXIT));
+        assert!(actions.just_released(EXIT));
+    }
+
+    #[test]
+    fn every_binding_round_trips_through_a_config_file() {
+        let map = InputMap::default()
+            .with_binding("Jump", Binding::Key(KeyCode::Space))
+            .with_binding("Jump", Binding::Mouse(MouseButton::Left))
+            .with_binding("Jump", Binding::GamepadButton(GamepadButtonType::South))
+            .with_binding("Fire", Binding::Mouse(MouseButton::Other(7)))
+            .with_binding(
+                "MoveX",
+                Binding::KeyAxis {
+                    negative: KeyCode::KeyA,
+                    positive: KeyCode::KeyD,
+                },
+            )
+            .with_binding(
+                "MoveX",
+                Binding::GamepadAxis {
+                    axis: GamepadAxisType::LeftStickX,
+                    dead_zone: 0.2,
+                },
+            )
+            .with_binding(
+                "Save",
+                Binding::Chord(vec![
+                    Binding::Key(KeyCode::ControlLeft),
+                    Binding::Key(KeyCode::KeyS),
+                ]),
+            );
+
+        let path = std::env::temp_dir().join("mush-input-map.txt");
+        map.save(&path).unwrap();
+        let loaded = InputMap::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, map);
+        assert_eq!(
+            map.to_string(),
+            "Fire: mouse 7\n\
+             Jump: key Space, mouse Left, pad South\n\
+             MoveX: keys KeyA KeyD, axis LeftStickX 0.2\n\
+             Save: chord key ControlLeft + key KeyS\n"
+        );
+    }
+
+    #[test]
+    fn axes_without_a_dead_zone_get_the_default() {
+        let map: InputMap = "Look: axis RightStickY".parse().unwrap();
+
+        assert_eq!(
+            map.bindings("Look"),
+            [Binding::GamepadAxis {
+                axis: GamepadAxisType::RightStickY,
+                dead_zone: DEFAULT_DEAD_ZONE,
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_their_line() {
+        let error = "# controls\nExit: key Escape\nJump: key Spcae\n"
+            .parse::<InputMap>()
+            .unwrap_err()
+            .to_string();
+
+        assert_eq!(error, "line 3: Unknown key Spcae");
+    }
+}
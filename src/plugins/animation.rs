@@ -0,0 +1,315 @@
+use std::{collections::HashMap, ops::Range};
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    event::{event_update_system, Event, EventWriter, Events},
+    schedule::IntoSystemConfigs as _,
+    system::{Query, Res},
+};
+
+use crate::{application::UpdateSchedule, resources::Time};
+
+use super::{texture_atlas::TextureAtlasSprite, App, Plugin};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AnimationMode {
+    /// Stops on the last frame and sends [`AnimationFinished`]
+    Once,
+    #[default]
+    Loop,
+    /// Plays forward, then backward, and so on
+    PingPong,
+}
+
+/// A range of frames in a texture atlas.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnimationClip {
+    /// Atlas indices, shown in order
+    pub frames: Range<usize>,
+    /// Seconds each frame is shown
+    pub frame_duration: f32,
+    pub mode: AnimationMode,
+}
+
+impl AnimationClip {
+    pub fn new(frames: Range<usize>, frame_duration: f32, mode: AnimationMode) -> Self {
+        Self {
+            frames,
+            frame_duration,
+            mode,
+        }
+    }
+}
+
+/// Sent when a clip in [`AnimationMode::Once`] reached its last frame.
+#[derive(Event, Clone, Debug)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: String,
+}
+
+/// Named clips that drive the [`TextureAtlasSprite`] index of the same entity.
+#[derive(Component, Clone, Debug)]
+pub struct SpriteAnimation {
+    clips: HashMap<String, AnimationClip>,
+    current: Option<String>,
+    /// position in the current clip's frames
+    frame: usize,
+    /// seconds the current frame has been shown
+    elapsed: f32,
+    /// ping pong clips are playing backward
+    reversed: bool,
+    finished: bool,
+    /// Playback speed multiplier
+    pub speed: f32,
+}
+
+impl Default for SpriteAnimation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteAnimation {
+    pub fn new() -> Self {
+        Self {
+            clips: HashMap::new(),
+            current: None,
+            frame: 0,
+            elapsed: 0.0,
+            reversed: false,
+            finished: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn with_clip(mut self, name: impl Into<String>, clip: AnimationClip) -> Self {
+        self.add_clip(name, clip);
+        self
+    }
+
+    pub fn add_clip(&mut self, name: impl Into<String>, clip: AnimationClip) {
+        self.clips.insert(name.into(), clip);
+    }
+
+    /// Switches to the named clip, starting at its first frame. Playing the
+    /// clip that is already playing does nothing, see [`Self::restart`].
+    pub fn play(&mut self, name: &str) {
+        if self.current.as_deref() == Some(name) {
+            return;
+        }
+
+        if !self.clips.contains_key(name) {
+            log::warn!("No animation clip named {name}");
+            return;
+        }
+
+        self.current = Some(name.to_owned());
+        self.restart();
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.reversed = false;
+        self.finished = false;
+    }
+
+    pub fn current_clip(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Whether a clip in [`AnimationMode::Once`] reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn clip(&self) -> Option<&AnimationClip> {
+        self.clips.get(self.current.as_ref()?)
+    }
+
+    /// Atlas index of the frame that is currently shown.
+    pub fn atlas_index(&self) -> Option<usize> {
+        Some(self.clip()?.frames.start + self.frame)
+    }
+
+    /// Advances by `delta` seconds, returns true if the clip finished during it.
+    fn tick(&mut self, delta: f32) -> bool {
+        let Some(clip) = self.clip().cloned() else {
+            return false;
+        };
+
+        // zero length frames would never advance past the loop below
+        if self.finished || clip.frame_duration <= 0.0 {
+            return false;
+        }
+
+        let last = clip.frames.len().saturating_sub(1);
+        self.elapsed += delta * self.speed;
+
+        while self.elapsed >= clip.frame_duration {
+            self.elapsed -= clip.frame_duration;
+
+            match clip.mode {
+                AnimationMode::Loop => {
+                    self.frame = if self.frame >= last {
+                        0
+                    } else {
+                        self.frame + 1
+                    }
+                }
+                AnimationMode::Once if self.frame >= last => {
+                    self.finished = true;
+                    return true;
+                }
+                AnimationMode::Once => self.frame += 1,
+                AnimationMode::PingPong => {
+                    if self.reversed && self.frame == 0 || !self.reversed && self.frame >= last {
+                        self.reversed = !self.reversed;
+                    }
+
+                    self.frame = match self.reversed {
+                        true => self.frame.saturating_sub(1),
+                        false => (self.frame + 1).min(last),
+                    };
+                }
+            }
+        }
+
+        false
+    }
+}
+
+pub fn animate_sprites_system(
+    time: Res<Time>,
+    mut animations: Query<(Entity, &mut SpriteAnimation, &mut TextureAtlasSprite)>,
+    mut finished: EventWriter<AnimationFinished>,
+) {
+    for (entity, mut animation, mut atlas_sprite) in &mut animations {
+        if animation.tick(time.delta()) {
+            finished.send(AnimationFinished {
+                entity,
+                clip: animation.current_clip().unwrap_or_default().to_owned(),
+            });
+        }
+
+        if let Some(index) = animation.atlas_index() {
+            if atlas_sprite.index != index {
+                atlas_sprite.index = index;
+            }
+        }
+    }
+}
+
+/// Advances sprite animations every update tick.
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.init_resource::<Events<AnimationFinished>>();
+
+        app.add_systems(
+            UpdateSchedule,
+            (
+                animate_sprites_system,
+                event_update_system::<AnimationFinished>,
+            )
+                .chain(),
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame durations are a power of two, so deltas add up exactly.
+    const FRAME: f32 = 0.25;
+
+    fn playing(frames: Range<usize>, mode: AnimationMode) -> SpriteAnimation {
+        let mut animation =
+            SpriteAnimation::new().with_clip("clip", AnimationClip::new(frames, FRAME, mode));
+        animation.play("clip");
+        animation
+    }
+
+    /// Atlas indices shown after each of `ticks` frame long ticks.
+    fn indices(animation: &mut SpriteAnimation, ticks: usize) -> Vec<usize> {
+        (0..ticks)
+            .map(|_| {
+                animation.tick(FRAME);
+                animation.atlas_index().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_the_first_frame() {
+        let mut animation = playing(4..7, AnimationMode::Loop);
+
+        assert_eq!(animation.atlas_index(), Some(4));
+        assert_eq!(indices(&mut animation, 5), vec![5, 6, 4, 5, 6]);
+    }
+
+    #[test]
+    fn ping_pong_turns_around_at_both_ends() {
+        let mut animation = playing(0..3, AnimationMode::PingPong);
+
+        assert_eq!(indices(&mut animation, 8), vec![1, 2, 1, 0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn single_frame_clips_stay_on_their_frame() {
+        for mode in [AnimationMode::Loop, AnimationMode::PingPong] {
+            let mut animation = playing(3..4, mode);
+            assert_eq!(indices(&mut animation, 3), vec![3; 3], "{mode:?}");
+        }
+
+        let mut animation = playing(3..4, AnimationMode::Once);
+        assert!(animation.tick(FRAME));
+        assert_eq!(animation.atlas_index(), Some(3));
+    }
+
+    #[test]
+    fn once_finishes_exactly_once_and_stays_on_the_last_frame() {
+        let mut animation = playing(0..3, AnimationMode::Once);
+
+        let finished: Vec<_> = (0..6).map(|_| animation.tick(FRAME)).collect();
+
+        assert_eq!(finished, vec![false, false, true, false, false, false]);
+        assert!(animation.is_finished());
+        assert_eq!(animation.atlas_index(), Some(2));
+    }
+
+    #[test]
+    fn long_deltas_skip_frames() {
+        let mut animation = playing(0..5, AnimationMode::Loop);
+        animation.tick(FRAME * 3.5);
+        assert_eq!(animation.atlas_index(), Some(3));
+
+        // the left over half frame counts towards the next one
+        animation.tick(FRAME * 0.5);
+        assert_eq!(animation.atlas_index(), Some(4));
+
+        let mut animation = playing(0..3, AnimationMode::PingPong);
+        animation.tick(FRAME * 5.0);
+        assert_eq!(animation.atlas_index(), Some(1));
+
+        let mut animation = playing(0..3, AnimationMode::Once);
+        assert!(animation.tick(FRAME * 10.0));
+        assert_eq!(animation.atlas_index(), Some(2));
+    }
+
+    #[test]
+    fn speed_scales_the_delta() {
+        let mut animation = playing(0..4, AnimationMode::Loop);
+        animation.speed = 2.0;
+
+        animation.tick(FRAME);
+        assert_eq!(animation.atlas_index(), Some(2));
+    }
+}
@@ -0,0 +1,577 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use bevy_ecs::{
+    event::{event_update_system, Event, Events},
+    schedule::{IntoSystemConfigs as _, Schedule},
+    system::Resource,
+    world::World,
+};
+
+use crate::application::RenderSchedule;
+
+use super::{rendering::RenderStage, App, Plugin};
+
+/// Something that can be loaded from a file by the [`AssetServer`].
+pub trait Asset: Send + Sync + Sized + 'static {
+    fn load(bytes: &[u8], context: &mut LoadContext) -> Result<Self, anyhow::Error>;
+}
+
+/// Passed to [`Asset::load`]. Lets assets read the files they reference and
+/// hand out further assets they produced while loading, like the packed image
+/// of a texture atlas.
+pub struct LoadContext<'a> {
+    path: &'a Path,
+    id: u64,
+    /// handles of labeled assets, kept so reloads replace instead of duplicate them
+    labels: &'a mut HashMap<(u64, String), u64>,
+    labeled: Vec<LabeledAsset>,
+}
+
+impl<'a> LoadContext<'a> {
+    pub(crate) fn new(
+        path: &'a Path,
+        id: u64,
+        labels: &'a mut HashMap<(u64, String), u64>,
+    ) -> Self {
+        Self {
+            path,
+            id,
+            labels,
+            labeled: Vec::new(),
+        }
+    }
+
+    /// The file that is being loaded.
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Reads a file relative to the directory of the file being loaded.
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>, anyhow::Error> {
+        let path = self
+            .path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path.as_ref());
+
+        std::fs::read(&path)
+            .map_err(|error| anyhow::anyhow!("Failed to read {}: {error}", path.display()))
+    }
+
+    /// Adds an asset that is moved into the world together with the asset being
+    /// loaded. The same label returns the same handle when the file is reloaded.
+    pub fn add_labeled_asset<T: Asset>(&mut self, label: &str, asset: T) -> Handle<T> {
+        let id = *self
+            .labels
+            .entry((self.id, label.to_owned()))
+            .or_insert_with(|| Handle::<T>::new().id);
+
+        self.labeled.push(LabeledAsset {
+            id,
+            asset: Box::new(asset),
+            insert: insert_erased::<T>,
+        });
+
+        Handle::from_id(id)
+    }
+}
+
+/// Typed reference to an asset, cheap to copy and valid before the asset is loaded.
+pub struct Handle<T> {
+    id: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// Creates a handle no other asset uses.
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self::from_id(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn from_id(id: u64) -> Self {
+        Self {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Default for Handle<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// manual impls, deriving would require T to implement them as well
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> PartialOrd for Handle<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Handle<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = std::any::type_name::<T>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+
+        write!(f, "Handle<{name}>({})", self.id)
+    }
+}
+
+/// Loaded assets of one type.
+#[derive(Resource)]
+pub struct Assets<T: Asset>(HashMap<Handle<T>, T>);
+
+impl<T: Asset> Default for Assets<T> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<T: Asset> Assets<T> {
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.0.get(&handle)
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.0.get_mut(&handle)
+    }
+
+    /// Adds an asset that was not loaded from disk. Remember to send an
+    /// [`AssetEvent::Added`] if systems need to pick it up.
+    pub fn add(&mut self, asset: T) -> Handle<T> {
+        let handle = Handle::new();
+        self.0.insert(handle, asset);
+
+        handle
+    }
+
+    pub fn insert(&mut self, handle: Handle<T>, asset: T) -> Option<T> {
+        self.0.insert(handle, asset)
+    }
+
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        self.0.remove(&handle)
+    }
+}
+
+#[derive(Event)]
+pub enum AssetEvent<T: Asset> {
+    Added(Handle<T>),
+    Modified(Handle<T>),
+}
+
+#[derive(Clone, Debug)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(Arc<anyhow::Error>),
+}
+
+type ErasedAsset = Box<dyn Any + Send>;
+type LoadFn = fn(&[u8], &mut LoadContext) -> Result<ErasedAsset, anyhow::Error>;
+type InsertFn = fn(&mut World, u64, ErasedAsset);
+
+/// Requested paths with the asset type they were loaded as, a file can be
+/// loaded as more than one type.
+type TrackedAssets = HashMap<(PathBuf, TypeId), LoadRequest>;
+
+/// Type erased loading job, the function pointers know the concrete asset type.
+#[derive(Clone)]
+struct LoadRequest {
+    id: u64,
+    path: PathBuf,
+    load: LoadFn,
+    insert: InsertFn,
+}
+
+struct LoadResult {
+    id: u64,
+    path: PathBuf,
+    result: Result<ErasedAsset, anyhow::Error>,
+    insert: InsertFn,
+    labeled: Vec<LabeledAsset>,
+}
+
+struct LabeledAsset {
+    id: u64,
+    asset: ErasedAsset,
+    insert: InsertFn,
+}
+
+/// How often watched files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Loads assets relative to its root directory on a background thread.
+/// Finished assets are moved into their [`Assets`] resource before rendering.
+///
+/// When watching for changes, modified files are loaded again and replace the
+/// old asset, sending [`AssetEvent::Modified`]. A failed reload keeps the old asset.
+#[derive(Resource)]
+pub struct AssetServer {
+    root: PathBuf,
+    requests: Mutex<Sender<LoadRequest>>,
+    results: Mutex<Receiver<LoadResult>>,
+    /// every path that was requested, so it can be reloaded
+    tracked: Arc<Mutex<TrackedAssets>>,
+    states: Mutex<HashMap<u64, LoadState>>,
+}
+
+impl AssetServer {
+    pub fn new(root: impl Into<PathBuf>, watch_for_changes: bool) -> Self {
+        let (request_sender, request_receiver) = mpsc::channel::<LoadRequest>();
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("asset loader".into())
+            .spawn(move || {
+                let mut labels = HashMap::new();
+
+                for request in request_receiver {
+                    let mut context = LoadContext::new(&request.path, request.id, &mut labels);
+
+                    let result = std::fs::read(&request.path)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|bytes| (request.load)(&bytes, &mut context));
+                    let labeled = context.labeled;
+
+                    let result = LoadResult {
+                        id: request.id,
+                        path: request.path,
+                        result,
+                        insert: request.insert,
+                        labeled,
+                    };
+
+                    if result_sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn asset loader thread");
+
+        let tracked = Arc::new(Mutex::new(HashMap::new()));
+
+        if watch_for_changes {
+            let tracked = Arc::downgrade(&tracked);
+            let requests = request_sender.clone();
+
+            std::thread::Builder::new()
+                .name("asset watcher".into())
+                .spawn(move || watch_for_changes_loop(tracked, requests))
+                .expect("failed to spawn asset watcher thread");
+        }
+
+        Self {
+            root: root.into(),
+            requests: Mutex::new(request_sender),
+            results: Mutex::new(result_receiver),
+            tracked,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Starts loading the asset at `path`, relative to the asset root. Loading
+    /// the same path as the same type again returns the same handle.
+    pub fn load<T: Asset>(&self, path: impl AsRef<Path>) -> Handle<T> {
+        let key = (self.root.join(path), TypeId::of::<T>());
+
+        let mut tracked = self.tracked.lock().unwrap();
+        if let Some(request) = tracked.get(&key) {
+            return Handle::from_id(request.id);
+        }
+
+        let handle = Handle::<T>::new();
+        let request = LoadRequest {
+            id: handle.id,
+            path: key.0.clone(),
+            load: load_erased::<T>,
+            insert: insert_erased::<T>,
+        };
+
+        tracked.insert(key, request.clone());
+        drop(tracked);
+
+        self.request(request);
+
+        handle
+    }
+
+    /// Returns a handle for an asset the caller already has, e.g. from
+    /// `include_str!`, that is loaded from `path` whenever the file changes.
+    /// `path` is not relative to the asset root.
+    pub fn track<T: Asset>(&self, path: impl Into<PathBuf>) -> Handle<T> {
+        let key = (path.into(), TypeId::of::<T>());
+
+        let mut tracked = self.tracked.lock().unwrap();
+        if let Some(request) = tracked.get(&key) {
+            return Handle::from_id(request.id);
+        }
+
+        let handle = Handle::<T>::new();
+        let request = LoadRequest {
+            id: handle.id,
+            path: key.0.clone(),
+            load: load_erased::<T>,
+            insert: insert_erased::<T>,
+        };
+
+        tracked.insert(key, request);
+
+        handle
+    }
+
+    fn request(&self, request: LoadRequest) {
+        let id = request.id;
+        self.states.lock().unwrap().insert(id, LoadState::Loading);
+
+        if self.requests.lock().unwrap().send(request).is_err() {
+            let error = anyhow::anyhow!("Asset loader thread stopped");
+            self.states
+                .lock()
+                .unwrap()
+                .insert(id, LoadState::Failed(Arc::new(error)));
+        }
+    }
+
+    /// None for handles that were never loaded through this server.
+    pub fn load_state<T>(&self, handle: Handle<T>) -> Option<LoadState> {
+        self.states.lock().unwrap().get(&handle.id).cloned()
+    }
+
+    /// Whether any asset is still being loaded.
+    pub fn is_loading(&self) -> bool {
+        self.states
+            .lock()
+            .unwrap()
+            .values()
+            .any(|state| matches!(state, LoadState::Loading))
+    }
+}
+
+/// Polls the modification time of every tracked file and requests a reload when
+/// it changes. Stops once the asset server is dropped.
+fn watch_for_changes_loop(tracked: Weak<Mutex<TrackedAssets>>, requests: Sender<LoadRequest>) {
+    let mut modified_times = HashMap::new();
+
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        let Some(tracked) = tracked.upgrade() else {
+            return;
+        };
+
+        let tracked: Vec<_> = tracked.lock().unwrap().values().cloned().collect();
+
+        for request in tracked {
+            let Ok(modified) = std::fs::metadata(&request.path).and_then(|m| m.modified()) else {
+                continue;
+            };
+
+            match modified_times.insert(request.id, modified) {
+                Some(previous) if previous != modified => {
+                    log::info!("Reloading {}", request.path.display());
+
+                    if requests.send(request).is_err() {
+                        return;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn load_erased<T: Asset>(
+    bytes: &[u8],
+    context: &mut LoadContext,
+) -> Result<ErasedAsset, anyhow::Error> {
+    Ok(Box::new(T::load(bytes, context)?))
+}
+
+fn insert_erased<T: Asset>(world: &mut World, id: u64, asset: ErasedAsset) {
+    let asset = *asset
+        .downcast::<T>()
+        .expect("loaded asset has the requested type");
+    let handle = Handle::from_id(id);
+
+    let previous = world
+        .get_resource_or_insert_with(Assets::<T>::default)
+        .insert(handle, asset);
+
+    let event = match previous {
+        Some(_) => AssetEvent::Modified(handle),
+        None => AssetEvent::Added(handle),
+    };
+
+    world
+        .get_resource_or_insert_with(Events::<AssetEvent<T>>::default)
+        .send(event);
+}
+
+/// Moves finished loads into the world.
+pub fn process_loaded_assets_system(world: &mut World) {
+    let results: Vec<_> = world
+        .resource::<AssetServer>()
+        .results
+        .lock()
+        .unwrap()
+        .try_iter()
+        .collect();
+
+    for LoadResult {
+        id,
+        path,
+        result,
+        insert,
+        labeled,
+    } in results
+    {
+        let state = match result {
+            Ok(asset) => {
+                log::info!("Loaded {}", path.display());
+
+                // labeled assets first, the asset that references them may
+                // look them up as soon as it is added
+                for labeled in labeled {
+                    (labeled.insert)(world, labeled.id, labeled.asset);
+                }
+
+                insert(world, id, asset);
+                LoadState::Loaded
+            }
+            Err(error) => {
+                log::error!("Failed to load {}: {error}", path.display());
+                LoadState::Failed(Arc::new(error))
+            }
+        };
+
+        world
+            .resource::<AssetServer>()
+            .states
+            .lock()
+            .unwrap()
+            .insert(id, state);
+    }
+}
+
+/// Registers storage and events for an asset type, so systems can read them
+/// before the first asset of that type finished loading.
+pub fn init_asset<T: Asset>(world: &mut World, schedule: &mut Schedule) {
+    world.init_resource::<Assets<T>>();
+    world.init_resource::<Events<AssetEvent<T>>>();
+
+    schedule.add_systems(event_update_system::<AssetEvent<T>>.after(RenderStage::Flush));
+}
+
+pub struct AssetPlugin {
+    pub root: PathBuf,
+    /// Reload assets when their files change, on by default in debug builds
+    pub watch_for_changes: bool,
+}
+
+impl Default for AssetPlugin {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("assets"),
+            watch_for_changes: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl Plugin for AssetPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.insert_resource(AssetServer::new(self.root, self.watch_for_changes));
+
+        app.add_systems(
+            RenderSchedule,
+            process_loaded_assets_system.before(RenderStage::Prepare),
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Text;
+    struct Bytes;
+
+    impl Asset for Text {
+        fn load(_bytes: &[u8], _context: &mut LoadContext) -> Result<Self, anyhow::Error> {
+            Ok(Self)
+        }
+    }
+
+    impl Asset for Bytes {
+        fn load(_bytes: &[u8], _context: &mut LoadContext) -> Result<Self, anyhow::Error> {
+            Ok(Self)
+        }
+    }
+
+    #[test]
+    fn handles_are_shared_per_path_and_type() {
+        let server = AssetServer::new("assets", false);
+
+        let text = server.load::<Text>("file.txt");
+        let bytes = server.load::<Bytes>("file.txt");
+
+        assert_eq!(server.load::<Text>("file.txt").id(), text.id());
+        assert_eq!(server.load::<Bytes>("file.txt").id(), bytes.id());
+        assert_ne!(text.id(), bytes.id());
+    }
+}
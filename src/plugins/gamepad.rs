@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy_ecs::{
+    event::{Event, Events},
+    system::Resource,
+    world::World,
+};
+
+use super::input::Input;
+
+/// Id of a connected gamepad, assigned by the input backend.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Gamepad(pub usize);
+
+/// Buttons by position, named after the xbox layout for the shoulder buttons.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadButtonType {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButtonType {
+    pub const ALL: [Self; 17] = [
+        Self::South,
+        Self::East,
+        Self::North,
+        Self::West,
+        Self::LeftBumper,
+        Self::RightBumper,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+        Self::Select,
+        Self::Start,
+        Self::Mode,
+        Self::LeftThumb,
+        Self::RightThumb,
+        Self::DPadUp,
+        Self::DPadDown,
+        Self::DPadLeft,
+        Self::DPadRight,
+    ];
+}
+
+/// Sticks go from -1 to 1, up being positive. Triggers go from 0 to 1.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadAxisType {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxisType {
+    pub const ALL: [Self; 6] = [
+        Self::LeftStickX,
+        Self::LeftStickY,
+        Self::RightStickX,
+        Self::RightStickY,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+    ];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GamepadButton {
+    pub gamepad: Gamepad,
+    pub button: GamepadButtonType,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GamepadAxis {
+    pub gamepad: Gamepad,
+    pub axis: GamepadAxisType,
+}
+
+/// Current value of analog inputs, like [`Input`](super::input::Input) for buttons.
+#[derive(Resource)]
+pub struct Axis<T: Copy + Eq + Hash + Send + Sync + 'static>(HashMap<T, f32>);
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Default for Axis<T> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Axis<T> {
+    pub fn get(&self, input: T) -> Option<f32> {
+        self.0.get(&input).copied()
+    }
+
+    pub fn set(&mut self, input: T, value: f32) {
+        self.0.insert(input, value);
+    }
+
+    pub fn remove(&mut self, input: T) -> Option<f32> {
+        self.0.remove(&input)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&T, &f32)> {
+        self.0.iter()
+    }
+}
+
+/// Connected gamepads and their names.
+#[derive(Resource, Default, Debug)]
+pub struct Gamepads(BTreeMap<Gamepad, String>);
+
+impl Gamepads {
+    pub fn contains(&self, gamepad: Gamepad) -> bool {
+        self.0.contains_key(&gamepad)
+    }
+
+    pub fn name(&self, gamepad: Gamepad) -> Option<&str> {
+        self.0.get(&gamepad).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Gamepad> + '_ {
+        self.0.keys().copied()
+    }
+}
+
+/// What an [`InputBackend`] reports about gamepads.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GamepadEvent {
+    Connected {
+        gamepad: Gamepad,
+        name: String,
+    },
+    Disconnected {
+        gamepad: Gamepad,
+    },
+    Button {
+        button: GamepadButton,
+        pressed: bool,
+    },
+    Axis {
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+#[derive(Event, Clone, PartialEq, Debug)]
+pub enum GamepadConnectionEvent {
+    Connected { gamepad: Gamepad, name: String },
+    Disconnected { gamepad: Gamepad },
+}
+
+/// Send this event to make a gamepad vibrate, it is forwarded to the
+/// [`InputBackend`] at the start of the next frame.
+#[derive(Event, Clone, PartialEq, Debug)]
+pub struct RumbleRequest {
+    pub gamepad: Gamepad,
+    /// Low frequency motor, 0 to 1
+    pub strong: f32,
+    /// High frequency motor, 0 to 1
+    pub weak: f32,
+    pub duration: Duration,
+}
+
+/// Source of gamepad input, winit does not provide any.
+pub trait InputBackend: Send + Sync + 'static {
+    /// Called once per frame before the update ticks, pushes everything that
+    /// happened since the previous call.
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+
+    fn rumble(&mut self, request: &RumbleRequest) {
+        log::debug!("Input backend does not support rumble, ignoring {request:?}");
+    }
+}
+
+/// The backend gamepad input is read from, none by default.
+#[derive(Resource)]
+pub struct ActiveInputBackend(pub Box<dyn InputBackend>);
+
+/// Backend replaying scripted events without any hardware, for tests. Clones
+/// share their script, keep one to push events and check rumble requests.
+#[derive(Clone, Default)]
+pub struct ScriptedBackend(Arc<Mutex<Script>>);
+
+#[derive(Default)]
+struct Script {
+    frames: VecDeque<Vec<GamepadEvent>>,
+    rumble_requests: Vec<RumbleRequest>,
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events reported by the next poll that has not got any yet, one call
+    /// per frame.
+    pub fn push_frame(&self, events: impl IntoIterator<Item = GamepadEvent>) {
+        self.0
+            .lock()
+            .unwrap()
+            .frames
+            .push_back(events.into_iter().collect());
+    }
+
+    /// Frames that have not been polled yet.
+    pub fn pending_frames(&self) -> usize {
+        self.0.lock().unwrap().frames.len()
+    }
+
+    pub fn rumble_requests(&self) -> Vec<RumbleRequest> {
+        self.0.lock().unwrap().rumble_requests.clone()
+    }
+}
+
+impl InputBackend for ScriptedBackend {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        if let Some(frame) = self.0.lock().unwrap().frames.pop_front() {
+            events.extend(frame);
+        }
+    }
+
+    fn rumble(&mut self, request: &RumbleRequest) {
+        self.0.lock().unwrap().rumble_requests.push(request.clone());
+    }
+}
+
+/// Forwards rumble requests to the backend and returns what it reported.
+/// Called once per frame, before the update ticks.
+pub fn poll_input_backend(world: &mut World) -> Vec<GamepadEvent> {
+    let rumble_requests: Vec<_> = world
+        .resource_mut::<Events<RumbleRequest>>()
+        .drain()
+        .collect();
+
+    let mut events = Vec::new();
+
+    let Some(mut backend) = world.get_resource_mut::<ActiveInputBackend>() else {
+        return events;
+    };
+
+    for request in &rumble_requests {
+        backend.0.rumble(request);
+    }
+
+    backend.0.poll(&mut events);
+
+    events
+}
+
+/// Applies a backend event to [`Gamepads`], [`Input<GamepadButton>`] and
+/// [`Axis<GamepadAxis>`].
+pub fn apply_gamepad_event(world: &mut World, event: GamepadEvent) {
+    match event {
+        GamepadEvent::Connected { gamepad, name } => {
+            log::info!("Gamepad {} connected: {name}", gamepad.0);
+
+            world
+                .resource_mut::<Gamepads>()
+                .0
+                .insert(gamepad, name.clone());
+            world.send_event(GamepadConnectionEvent::Connected { gamepad, name });
+        }
+        GamepadEvent::Disconnected { gamepad } => {
+            log::info!("Gamepad {} disconnected", gamepad.0);
+
+            world.resource_mut::<Gamepads>().0.remove(&gamepad);
+
+            let mut buttons = world.resource_mut::<Input<GamepadButton>>();
+            let pressed: Vec<_> = buttons
+                .get_pressed()
+                .filter(|button| button.gamepad == gamepad)
+                .copied()
+                .collect();
+            for button in pressed {
+                buttons.release(button);
+            }
+
+            let mut axes = world.resource_mut::<Axis<GamepadAxis>>();
+            for axis in GamepadAxisType::ALL {
+                axes.remove(GamepadAxis { gamepad, axis });
+            }
+
+            world.send_event(GamepadConnectionEvent::Disconnected { gamepad });
+        }
+        GamepadEvent::Button { button, pressed } => {
+            let mut buttons = world.resource_mut::<Input<GamepadButton>>();
+
+            match pressed {
+                true => buttons.press(button),
+                false => buttons.release(button),
+            }
+        }
+        GamepadEvent::Axis { axis, value } => {
+            world.resource_mut::<Axis<GamepadAxis>>().set(axis, value);
+        }
+    }
+}
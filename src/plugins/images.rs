@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use bevy_ecs::{
+    event::EventReader,
+    system::{Res, ResMut, Resource},
+};
+
+use super::{
+    assets::{Asset, AssetEvent, Assets, Handle, LoadContext},
+    rendering::{WgpuDevice, WgpuQueue},
+};
+
+/// Cpu side image, uploaded to [`GpuImages`] whenever it is added to or
+/// modified in [`Assets<Image>`].
+pub struct Image(pub image::RgbaImage);
+
+impl Asset for Image {
+    fn load(bytes: &[u8], _context: &mut LoadContext) -> Result<Self, anyhow::Error> {
+        Ok(Self(image::load_from_memory(bytes)?.to_rgba8()))
+    }
+}
+
+pub struct GpuImage {
+    pub texture: wgpu::Texture,
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+}
+
+impl GpuImage {
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+        label: Option<&str>,
+    ) -> Self {
+        let dimensions = image.dimensions();
+
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        };
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTextureBase {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * dimensions.0),
+                rows_per_image: Some(dimensions.1),
+            },
+            size,
+        );
+
+        Self {
+            texture,
+            size,
+            format,
+        }
+    }
+
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+/// All images living on the gpu, addressed by their handle.
+#[derive(Resource, Default)]
+pub struct GpuImages(HashMap<Handle<Image>, GpuImage>);
+
+impl GpuImages {
+    pub fn get(&self, handle: Handle<Image>) -> Option<&GpuImage> {
+        self.0.get(&handle)
+    }
+
+    /// Creates an image that cameras can render into and sprites can sample from.
+    pub fn create_render_target(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Handle<Image> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render Target Image"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        self.add(GpuImage {
+            texture,
+            size,
+            format,
+        })
+    }
+
+    pub fn add(&mut self, image: GpuImage) -> Handle<Image> {
+        let handle = Handle::new();
+        self.0.insert(handle, image);
+
+        handle
+    }
+
+    pub fn insert(&mut self, handle: Handle<Image>, image: GpuImage) -> Option<GpuImage> {
+        self.0.insert(handle, image)
+    }
+
+    pub fn remove(&mut self, handle: Handle<Image>) -> Option<GpuImage> {
+        self.0.remove(&handle)
+    }
+}
+
+/// Uploads new and changed images to the gpu.
+pub fn prepare_images_system(
+    mut asset_events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    mut gpu_images: ResMut<GpuImages>,
+    device: Res<WgpuDevice>,
+    queue: Res<WgpuQueue>,
+) {
+    for event in asset_events.read() {
+        let (AssetEvent::Added(handle) | AssetEvent::Modified(handle)) = event;
+
+        let Some(image) = images.get(*handle) else {
+            continue;
+        };
+
+        let gpu_image = GpuImage::from_rgba(&device.0, &queue.0, &image.0, None);
+        gpu_images.insert(*handle, gpu_image);
+    }
+}
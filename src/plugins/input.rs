@@ -0,0 +1,431 @@
+use std::{collections::HashSet, fmt, hash::Hash};
+
+use bevy_ecs::{
+    event::{event_update_system, Event, Events},
+    schedule::{IntoSystemConfigs as _, SystemSet},
+    system::Resource,
+    world::World,
+};
+use glam::Vec2;
+use winit::{
+    event::{ElementState, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+pub use winit::event::MouseButton;
+
+use crate::application::UpdateSchedule;
+
+use super::{
+    actions::{parse_key, parse_mouse_button, parse_named},
+    gamepad::{
+        apply_gamepad_event, Axis, Gamepad, GamepadAxis, GamepadAxisType, GamepadButton,
+        GamepadButtonType, GamepadConnectionEvent, GamepadEvent, Gamepads, RumbleRequest,
+    },
+    App, Plugin,
+};
+
+/// Systems turning raw input into higher level state, like actions. Systems
+/// reading that state should run after it.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct InputSet;
+
+/// Pressed state of keys or buttons.
+///
+/// Window events arrive once per frame, but a frame can run any number of
+/// update ticks. `just_pressed` and `just_released` stay set until the end of
+/// the next tick, so every press is seen by exactly one tick, even if the frame
+/// runs none.
+#[derive(Resource)]
+pub struct Input<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Default for Input<T> {
+    fn default() -> Self {
+        Self {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Input<T> {
+    pub fn press(&mut self, input: T) {
+        if self.pressed.insert(input) {
+            self.just_pressed.insert(input);
+        }
+    }
+
+    pub fn release(&mut self, input: T) {
+        if self.pressed.remove(&input) {
+            self.just_released.insert(input);
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    pub fn pressed(&self, input: T) -> bool {
+        self.pressed.contains(&input)
+    }
+
+    pub fn any_pressed(&self, inputs: impl IntoIterator<Item = T>) -> bool {
+        inputs.into_iter().any(|input| self.pressed(input))
+    }
+
+    /// Pressed since the previous tick. Still true if it was released again
+    /// before this tick ran.
+    pub fn just_pressed(&self, input: T) -> bool {
+        self.just_pressed.contains(&input)
+    }
+
+    pub fn just_released(&self, input: T) -> bool {
+        self.just_released.contains(&input)
+    }
+
+    pub fn get_pressed(&self) -> impl Iterator<Item = &T> {
+        self.pressed.iter()
+    }
+
+    pub fn get_just_pressed(&self) -> impl Iterator<Item = &T> {
+        self.just_pressed.iter()
+    }
+
+    pub fn get_just_released(&self) -> impl Iterator<Item = &T> {
+        self.just_released.iter()
+    }
+
+    /// Called after every update tick.
+    pub fn clear(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+/// Cursor position in physical pixels from the top left of the window, None
+/// while the cursor is outside.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct CursorPosition(pub Option<Vec2>);
+
+/// Scrolled distance since the previous tick, in lines.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct ScrollDelta(pub Vec2);
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CursorMoved {
+    pub position: Vec2,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MouseWheel {
+    /// In lines, pixel deltas from touchpads are converted
+    pub delta: Vec2,
+}
+
+/// Text typed by the user, with keyboard layout and modifiers applied.
+#[derive(Event, Clone, Debug)]
+pub struct TextInput {
+    pub text: String,
+}
+
+/// Pixels per scrolled line, for touchpads reporting pixel deltas
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Everything that changes input state, from the window or the input backend.
+/// Applying the same events before the same ticks reproduces the same input,
+/// which is what input recordings rely on.
+#[derive(Clone, PartialEq, Debug)]
+pub enum InputEvent {
+    Key {
+        key: KeyCode,
+        pressed: bool,
+    },
+    Mouse {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// In physical pixels from the top left of the window
+    CursorMoved(Vec2),
+    CursorLeft,
+    /// In lines
+    Scroll(Vec2),
+    Text(String),
+    /// Releases all keys and mouse buttons, their releases go to the other window
+    FocusLost,
+    Gamepad(GamepadEvent),
+}
+
+impl InputEvent {
+    /// The input a window event carries, key presses can also carry text.
+    pub fn from_window_event(event: &WindowEvent) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    events.push(InputEvent::Key {
+                        key,
+                        pressed: event.state == ElementState::Pressed,
+                    });
+                }
+
+                if let (ElementState::Pressed, Some(text)) = (event.state, &event.text) {
+                    events.push(InputEvent::Text(text.to_string()));
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                events.push(InputEvent::Mouse {
+                    button: *button,
+                    pressed: *state == ElementState::Pressed,
+                });
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                events.push(InputEvent::CursorMoved(Vec2::new(
+                    position.x as f32,
+                    position.y as f32,
+                )));
+            }
+            WindowEvent::CursorLeft { .. } => events.push(InputEvent::CursorLeft),
+            WindowEvent::MouseWheel { delta, .. } => {
+                events.push(InputEvent::Scroll(match delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y),
+                    MouseScrollDelta::PixelDelta(position) => {
+                        Vec2::new(position.x as f32, position.y as f32) / PIXELS_PER_LINE
+                    }
+                }));
+            }
+            WindowEvent::Focused(false) => events.push(InputEvent::FocusLost),
+            _ => (),
+        }
+
+        events
+    }
+}
+
+/// Moves an input event into the input resources and events.
+pub fn apply_input_event(world: &mut World, event: InputEvent) {
+    match event {
+        InputEvent::Key { key, pressed } => {
+            let mut keys = world.resource_mut::<Input<KeyCode>>();
+
+            match pressed {
+                true => keys.press(key),
+                false => keys.release(key),
+            }
+        }
+        InputEvent::Mouse { button, pressed } => {
+            let mut buttons = world.resource_mut::<Input<MouseButton>>();
+
+            match pressed {
+                true => buttons.press(button),
+                false => buttons.release(button),
+            }
+        }
+        InputEvent::CursorMoved(position) => {
+            world.resource_mut::<CursorPosition>().0 = Some(position);
+            world.send_event(CursorMoved { position });
+        }
+        InputEvent::CursorLeft => {
+            world.resource_mut::<CursorPosition>().0 = None;
+        }
+        InputEvent::Scroll(delta) => {
+            world.resource_mut::<ScrollDelta>().0 += delta;
+            world.send_event(MouseWheel { delta });
+        }
+        InputEvent::Text(text) => {
+            world.send_event(TextInput { text });
+        }
+        InputEvent::FocusLost => {
+            world.resource_mut::<Input<KeyCode>>().release_all();
+            world.resource_mut::<Input<MouseButton>>().release_all();
+        }
+        InputEvent::Gamepad(event) => apply_gamepad_event(world, event),
+    }
+}
+
+fn pressed_name(pressed: bool) -> &'static str {
+    match pressed {
+        true => "pressed",
+        false => "released",
+    }
+}
+
+fn parse_pressed(name: &str) -> Result<bool, anyhow::Error> {
+    match name {
+        "pressed" => Ok(true),
+        "released" => Ok(false),
+        _ => anyhow::bail!("Expected pressed or released, got {name}"),
+    }
+}
+
+/// Keeps text on a single line
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputEvent::Key { key, pressed } => write!(f, "key {key:?} {}", pressed_name(*pressed)),
+            InputEvent::Mouse {
+                button: MouseButton::Other(button),
+                pressed,
+            } => write!(f, "mouse {button} {}", pressed_name(*pressed)),
+            InputEvent::Mouse { button, pressed } => {
+                write!(f, "mouse {button:?} {}", pressed_name(*pressed))
+            }
+            InputEvent::CursorMoved(position) => write!(f, "cursor {} {}", position.x, position.y),
+            InputEvent::CursorLeft => write!(f, "cursor-left"),
+            InputEvent::Scroll(delta) => write!(f, "scroll {} {}", delta.x, delta.y),
+            InputEvent::Text(text) => write!(f, "text {}", escape(text)),
+            InputEvent::FocusLost => write!(f, "focus-lost"),
+            InputEvent::Gamepad(GamepadEvent::Connected { gamepad, name }) => {
+                write!(f, "pad-connected {} {}", gamepad.0, escape(name))
+            }
+            InputEvent::Gamepad(GamepadEvent::Disconnected { gamepad }) => {
+                write!(f, "pad-disconnected {}", gamepad.0)
+            }
+            InputEvent::Gamepad(GamepadEvent::Button { button, pressed }) => write!(
+                f,
+                "pad-button {} {:?} {}",
+                button.gamepad.0,
+                button.button,
+                pressed_name(*pressed)
+            ),
+            InputEvent::Gamepad(GamepadEvent::Axis { axis, value }) => {
+                write!(f, "pad-axis {} {:?} {value}", axis.gamepad.0, axis.axis)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for InputEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        // text keeps its spaces, everything else is split into words
+        let (kind, rest) = source.split_once(' ').unwrap_or((source, ""));
+
+        match kind {
+            "text" => return Ok(InputEvent::Text(unescape(rest))),
+            "pad-connected" => {
+                let (gamepad, name) = rest.split_once(' ').unwrap_or((rest, ""));
+
+                return Ok(InputEvent::Gamepad(GamepadEvent::Connected {
+                    gamepad: Gamepad(gamepad.parse()?),
+                    name: unescape(name),
+                }));
+            }
+            _ => (),
+        }
+
+        let words: Vec<_> = source.split_whitespace().collect();
+
+        let event = match words.as_slice() {
+            ["key", key, pressed] => InputEvent::Key {
+                key: parse_key(key)?,
+                pressed: parse_pressed(pressed)?,
+            },
+            ["mouse", button, pressed] => InputEvent::Mouse {
+                button: parse_mouse_button(button)?,
+                pressed: parse_pressed(pressed)?,
+            },
+            ["cursor", x, y] => InputEvent::CursorMoved(Vec2::new(x.parse()?, y.parse()?)),
+            ["cursor-left"] => InputEvent::CursorLeft,
+            ["scroll", x, y] => InputEvent::Scroll(Vec2::new(x.parse()?, y.parse()?)),
+            ["focus-lost"] => InputEvent::FocusLost,
+            ["pad-disconnected", gamepad] => InputEvent::Gamepad(GamepadEvent::Disconnected {
+                gamepad: Gamepad(gamepad.parse()?),
+            }),
+            ["pad-button", gamepad, button, pressed] => InputEvent::Gamepad(GamepadEvent::Button {
+                button: GamepadButton {
+                    gamepad: Gamepad(gamepad.parse()?),
+                    button: parse_named(button, &GamepadButtonType::ALL)?,
+                },
+                pressed: parse_pressed(pressed)?,
+            }),
+            ["pad-axis", gamepad, axis, value] => InputEvent::Gamepad(GamepadEvent::Axis {
+                axis: GamepadAxis {
+                    gamepad: Gamepad(gamepad.parse()?),
+                    axis: parse_named(axis, &GamepadAxisType::ALL)?,
+                },
+                value: value.parse()?,
+            }),
+            _ => anyhow::bail!("Unknown input event {source}"),
+        };
+
+        Ok(event)
+    }
+}
+
+/// Resets the per tick input state, called after every update tick.
+pub fn finish_input_tick(world: &mut World) {
+    world.resource_mut::<Input<KeyCode>>().clear();
+    world.resource_mut::<Input<MouseButton>>().clear();
+    world.resource_mut::<Input<GamepadButton>>().clear();
+    world.resource_mut::<ScrollDelta>().0 = Vec2::ZERO;
+}
+
+/// Keyboard, mouse and gamepad state, updated every update tick.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.init_resource::<Input<KeyCode>>();
+        world.init_resource::<Input<MouseButton>>();
+        world.init_resource::<Input<GamepadButton>>();
+        world.init_resource::<Axis<GamepadAxis>>();
+        world.init_resource::<Gamepads>();
+        world.init_resource::<Events<GamepadConnectionEvent>>();
+        world.init_resource::<Events<RumbleRequest>>();
+        world.init_resource::<CursorPosition>();
+        world.init_resource::<ScrollDelta>();
+        world.init_resource::<Events<CursorMoved>>();
+        world.init_resource::<Events<MouseWheel>>();
+        world.init_resource::<Events<TextInput>>();
+
+        // updated per tick instead of per frame, events sent between frames are
+        // kept until the ticks after them ran
+        app.add_systems(
+            UpdateSchedule,
+            (
+                event_update_system::<CursorMoved>,
+                event_update_system::<MouseWheel>,
+                event_update_system::<TextInput>,
+                event_update_system::<GamepadConnectionEvent>,
+            )
+                .before(InputSet),
+        );
+
+        Ok(())
+    }
+}
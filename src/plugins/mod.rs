@@ -1,6 +1,188 @@
+pub mod actions;
+pub mod animation;
+pub mod assets;
+pub mod gamepad;
+pub mod images;
+pub mod input;
+pub mod pixel_perfect;
 pub mod rendering;
+pub mod screenshot;
 pub mod sprites;
+pub mod texture_atlas;
+pub mod transform;
 
-pub trait Plugin {
-    fn build(self, world: &mut bevy_ecs::world::World, schedule: &mut bevy_ecs::schedule::Schedule);
+use std::any::{type_name, TypeId};
+
+use bevy_ecs::{
+    schedule::{IntoSystemConfigs, Schedule, ScheduleLabel},
+    world::World,
+};
+
+pub trait Plugin: 'static {
+    /// Adds the plugin's resources and systems. Dependencies are built already,
+    /// an error stops building the application.
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error>;
+
+    /// Plugins that have to be added too, they are built before this one.
+    fn dependencies(&self) -> Vec<PluginId> {
+        Vec::new()
+    }
+}
+
+/// A plugin type, for declaring dependencies.
+#[derive(Clone, Copy, Debug)]
+pub struct PluginId {
+    type_id: TypeId,
+    name: &'static str,
+}
+
+impl PluginId {
+    pub fn of<P: Plugin>() -> Self {
+        Self {
+            type_id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl PartialEq for PluginId {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_id == other.type_id
+    }
+}
+
+impl Eq for PluginId {}
+
+/// A plugin waiting to be built. Boxed plugins lose their type, so the id is
+/// taken while it is still known.
+pub(crate) struct PendingPlugin {
+    id: PluginId,
+    plugin: Box<dyn Plugin>,
+}
+
+impl PendingPlugin {
+    pub fn new<P: Plugin>(plugin: P) -> Self {
+        Self {
+            id: PluginId::of::<P>(),
+            plugin: Box::new(plugin),
+        }
+    }
+
+    pub fn id(&self) -> PluginId {
+        self.id
+    }
+}
+
+/// What plugins are built into, the world with all schedules of the
+/// application, see [`crate::application`] for their labels.
+pub struct App<'a> {
+    world: &'a mut World,
+    built: &'a mut Vec<PluginId>,
+}
+
+impl<'a> App<'a> {
+    pub(crate) fn new(world: &'a mut World, built: &'a mut Vec<PluginId>) -> Self {
+        Self { world, built }
+    }
+
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.world
+    }
+
+    pub fn add_systems<M>(
+        &mut self,
+        label: impl ScheduleLabel,
+        systems: impl IntoSystemConfigs<M>,
+    ) -> &mut Self {
+        self.schedule_scope(label, |_, schedule| {
+            schedule.add_systems(systems);
+        });
+        self
+    }
+
+    /// Runs `function` with the schedule taken out of the world, e.g. for
+    /// [`init_asset`](assets::init_asset).
+    pub fn schedule_scope<R>(
+        &mut self,
+        label: impl ScheduleLabel,
+        function: impl FnOnce(&mut World, &mut Schedule) -> R,
+    ) -> R {
+        self.world.schedule_scope(label, function)
+    }
+
+    pub fn has_plugin<P: Plugin>(&self) -> bool {
+        self.built.contains(&PluginId::of::<P>())
+    }
+
+    /// Builds `plugins` in the order they were added, except that every plugin
+    /// waits for its dependencies. A plugin type can only be built once.
+    pub(crate) fn build_plugins(
+        &mut self,
+        mut plugins: Vec<PendingPlugin>,
+    ) -> Result<(), anyhow::Error> {
+        for (index, pending) in plugins.iter().enumerate() {
+            let added_before = plugins[..index].iter().any(|other| other.id == pending.id);
+
+            if added_before || self.built.contains(&pending.id) {
+                anyhow::bail!("Plugin {} was added twice", pending.id.name);
+            }
+        }
+
+        while !plugins.is_empty() {
+            let ready = plugins.iter().position(|pending| {
+                pending
+                    .plugin
+                    .dependencies()
+                    .iter()
+                    .all(|dependency| self.built.contains(dependency))
+            });
+
+            let Some(ready) = ready else {
+                return Err(self.unbuildable(&plugins));
+            };
+
+            let PendingPlugin { id, plugin } = plugins.remove(ready);
+
+            log::debug!("Building plugin {}", id.name);
+            plugin
+                .build(self)
+                .map_err(|error| anyhow::anyhow!("Failed to build plugin {}: {error}", id.name))?;
+            self.built.push(id);
+        }
+
+        Ok(())
+    }
+
+    fn unbuildable(&self, plugins: &[PendingPlugin]) -> anyhow::Error {
+        let pending: Vec<_> = plugins.iter().map(|plugin| plugin.id).collect();
+
+        let missing: Vec<_> = plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .plugin
+                    .dependencies()
+                    .into_iter()
+                    .filter(|dependency| !pending.contains(dependency))
+                    .map(|dependency| format!("{} requires {}", plugin.id.name, dependency.name))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        match missing.is_empty() {
+            true => {
+                let names: Vec<_> = pending.iter().map(|id| id.name).collect();
+                anyhow::anyhow!("Plugins depend on each other: {}", names.join(", "))
+            }
+            false => anyhow::anyhow!("Missing plugins: {}", missing.join(", ")),
+        }
+    }
 }
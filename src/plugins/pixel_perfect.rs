@@ -0,0 +1,336 @@
+use bevy_ecs::{
+    event::{EventReader, EventWriter},
+    schedule::IntoSystemConfigs as _,
+    system::{Res, ResMut, Resource},
+};
+use glam::{UVec2, Vec2};
+use wgpu::include_wgsl;
+
+use crate::application::RenderSchedule;
+
+use super::{
+    assets::{AssetEvent, AssetPlugin, AssetServer, Assets, Handle},
+    rendering::{
+        flush_render_system, load_builtin_shader, validated, Camera, CommandBufferFinishedEvent,
+        HeadlessTarget, RenderPlugin, RenderStage, Shader, SurfaceFrame, Viewport, WgpuConfig,
+        WgpuDevice,
+    },
+    App, Plugin, PluginId,
+};
+
+/// Offscreen texture that all window cameras draw into when rendering at a
+/// fixed low resolution. It is upscaled to the window by the largest integer
+/// factor that fits, the remaining space is letterboxed.
+#[derive(Resource)]
+pub struct LowResolutionTarget {
+    pub texture: wgpu::Texture,
+    pub size: UVec2,
+    /// Where the upscaled image ends up in the window, in physical pixels
+    pub window_viewport: Viewport,
+}
+
+impl LowResolutionTarget {
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Converts a window position, e.g. the cursor, into a pixel position in the
+    /// low resolution target. None inside the letterbox bars.
+    pub fn window_to_target(&self, position: Vec2) -> Option<Vec2> {
+        let viewport = self.window_viewport;
+        let relative = (position - viewport.position.as_vec2()) / viewport.size.as_vec2();
+
+        if relative.cmplt(Vec2::ZERO).any() || relative.cmpge(Vec2::ONE).any() {
+            return None;
+        }
+
+        Some(relative * self.size.as_vec2())
+    }
+
+    /// Converts a window position into a point on the z = 0 plane of `camera`,
+    /// which has to draw to the window.
+    pub fn window_to_world(&self, camera: &Camera, position: Vec2) -> Option<Vec2> {
+        camera.screen_to_world_2d(self.window_to_target(position)?)
+    }
+}
+
+/// Integer scaled and centered, falls back to scaling down if the window is
+/// smaller than the target.
+fn upscaled_viewport(size: UVec2, window_size: UVec2) -> Viewport {
+    let scale = window_size.as_vec2() / size.as_vec2();
+    let scale = scale.x.min(scale.y);
+    let scale = if scale >= 1.0 { scale.floor() } else { scale };
+
+    let upscaled = (size.as_vec2() * scale).as_uvec2().max(UVec2::ONE);
+
+    Viewport {
+        position: window_size.saturating_sub(upscaled) / 2,
+        size: upscaled,
+    }
+}
+
+#[derive(Resource)]
+struct PixelPerfectContext {
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// of the window, the pipeline draws into it
+    format: wgpu::TextureFormat,
+    shader_handle: Handle<Shader>,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Where `pixel_perfect.wgsl` is loaded from, relative to the asset root.
+const SHADER_PATH: &str = "shaders/pixel_perfect.wgsl";
+
+/// Renders all window cameras at `resolution` and upscales the result to the window.
+pub struct PixelPerfectPlugin {
+    pub resolution: UVec2,
+}
+
+impl Plugin for PixelPerfectPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        let device = &world.resource::<WgpuDevice>().0;
+        let config = &world.resource::<WgpuConfig>().0;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Low Resolution Target"),
+            size: wgpu::Extent3d {
+                width: self.resolution.x,
+                height: self.resolution.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Pixel Perfect Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // integer scaling keeps every texel the same size with nearest filtering
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pixel Perfect Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let shader =
+            device.create_shader_module(include_wgsl!("../../assets/shaders/pixel_perfect.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pixel Perfect Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let format = config.format;
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format);
+        let shader_handle = load_builtin_shader(world.resource::<AssetServer>(), SHADER_PATH);
+
+        let window_viewport =
+            upscaled_viewport(self.resolution, UVec2::new(config.width, config.height));
+
+        world.insert_resource(LowResolutionTarget {
+            texture,
+            size: self.resolution,
+            window_viewport,
+        });
+        world.insert_resource(PixelPerfectContext {
+            pipeline,
+            pipeline_layout,
+            format,
+            shader_handle,
+            bind_group,
+        });
+
+        // the blit has to be submitted after everything drawn into the low
+        // resolution target
+        app.add_systems(
+            RenderSchedule,
+            (
+                reload_pixel_perfect_shader_system.in_set(RenderStage::Prepare),
+                upscale_to_window_system
+                    .in_set(RenderStage::Flush)
+                    .before(flush_render_system),
+            ),
+        );
+
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![
+            PluginId::of::<RenderPlugin>(),
+            PluginId::of::<AssetPlugin>(),
+        ]
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Pixel Perfect Pipeline"),
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vertex_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fragment_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Rebuilds the pipeline when `pixel_perfect.wgsl` changed. Broken shaders are
+/// logged and the previous pipeline stays in use.
+fn reload_pixel_perfect_shader_system(
+    mut shader_events: EventReader<AssetEvent<Shader>>,
+    shaders: Res<Assets<Shader>>,
+    device: Res<WgpuDevice>,
+    mut context: ResMut<PixelPerfectContext>,
+) {
+    for event in shader_events.read() {
+        let (AssetEvent::Added(handle) | AssetEvent::Modified(handle)) = event;
+
+        let Some(shader) = shaders
+            .get(*handle)
+            .filter(|_| *handle == context.shader_handle)
+        else {
+            continue;
+        };
+
+        let rebuilt = validated(&device.0, || {
+            let module = device.0.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("pixel_perfect.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
+            });
+
+            create_pipeline(&device.0, &context.pipeline_layout, &module, context.format)
+        });
+
+        match rebuilt {
+            Ok(pipeline) => {
+                log::info!("Rebuilt pixel perfect pipeline");
+                context.pipeline = pipeline;
+            }
+            Err(error) => log::error!("Failed to rebuild pixel perfect pipeline: {error}"),
+        }
+    }
+}
+
+fn upscale_to_window_system(
+    device: Res<WgpuDevice>,
+    config: Res<WgpuConfig>,
+    surface_frame: Res<SurfaceFrame>,
+    headless_target: Option<Res<HeadlessTarget>>,
+    mut low_resolution_target: ResMut<LowResolutionTarget>,
+    context: Res<PixelPerfectContext>,
+    mut buffer_queue: EventWriter<CommandBufferFinishedEvent>,
+) {
+    let texture = match (&surface_frame.0, &headless_target) {
+        (Some(output), _) => &output.texture,
+        (None, Some(target)) => &target.0,
+        (None, None) => return,
+    };
+
+    let window_size = UVec2::new(config.0.width, config.0.height);
+    let viewport = upscaled_viewport(low_resolution_target.size, window_size);
+    if low_resolution_target.window_viewport != viewport {
+        low_resolution_target.window_viewport = viewport;
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut encoder = device
+        .0
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Upscale Low Resolution Target"),
+        });
+
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_viewport(
+            viewport.position.x as f32,
+            viewport.position.y as f32,
+            viewport.size.x as f32,
+            viewport.size.y as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_pipeline(&context.pipeline);
+        render_pass.set_bind_group(0, &context.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    buffer_queue.send(CommandBufferFinishedEvent(encoder.finish()));
+}
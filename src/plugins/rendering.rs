@@ -1,13 +1,29 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
 use bevy_ecs::{
+    change_detection::DetectChanges as _,
     component::Component,
+    entity::Entity,
     event::{Event, EventReader, Events},
     schedule::{IntoSystemConfigs as _, IntoSystemSetConfigs as _, Schedule, SystemSet},
-    system::{Query, Res, ResMut, Resource},
+    system::{Local, Query, Res, ResMut, Resource},
     world::World,
 };
-use winit::window::Window;
+use pollster::FutureExt as _;
+use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::application::ResizeEvent;
+use crate::application::{RenderSchedule, ResizeEvent};
+
+use super::{
+    assets::{init_asset, Asset, AssetServer, Handle, LoadContext},
+    images::{prepare_images_system, GpuImages, Image},
+    pixel_perfect::LowResolutionTarget,
+    transform::GlobalTransform,
+    App, Plugin,
+};
 
 #[derive(SystemSet, Clone, Hash, Eq, PartialEq, Debug)]
 pub enum RenderStage {
@@ -31,56 +47,278 @@ pub struct WgpuQueue(pub wgpu::Queue);
 #[derive(Resource)]
 pub struct WgpuConfig(pub wgpu::SurfaceConfiguration);
 
+/// How frames are presented to the window, Fifo by default. Can be changed at
+/// any time, modes the surface does not support fall back to Fifo. The mode in
+/// use is in [`WgpuConfig`].
+///
+/// The vsynced modes, Fifo, FifoRelaxed and AutoVsync, limit the frame rate to
+/// the display's, the application only limits it itself with the others.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WindowPresentMode(pub wgpu::PresentMode);
+
+/// Whether presenting in `mode` waits for the display.
+pub fn is_vsync(mode: wgpu::PresentMode) -> bool {
+    matches!(
+        mode,
+        wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed | wgpu::PresentMode::AutoVsync
+    )
+}
+
+/// `requested` if the surface supports it, Fifo otherwise. The auto modes
+/// always work, wgpu picks a supported mode for them.
+fn supported_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    match requested {
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => requested,
+        mode if supported.contains(&mode) => mode,
+        mode => {
+            log::warn!("Present mode {mode:?} is not supported, falling back to Fifo");
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct CameraBindGroupLayout(pub wgpu::BindGroupLayout);
 
-pub async fn init_render_schedule(
+/// WGSL source, loaded through the asset server so pipelines can be rebuilt
+/// when the file changes.
+pub struct Shader {
+    pub source: String,
+}
+
+impl Asset for Shader {
+    fn load(bytes: &[u8], _context: &mut LoadContext) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            source: String::from_utf8(bytes.to_vec())?,
+        })
+    }
+}
+
+/// Handle for a built-in shader that a file at `path` under the asset root
+/// can replace. The file is loaded and watched if it exists when this is
+/// called, the embedded source stays in use otherwise.
+pub fn load_builtin_shader(asset_server: &AssetServer, path: &str) -> Handle<Shader> {
+    let full_path = asset_server.root().join(path);
+
+    match full_path.exists() {
+        true => asset_server.load(path),
+        false => asset_server.track(full_path),
+    }
+}
+
+/// Runs `create` in a validation error scope, returning the error instead of
+/// panicking. Used to rebuild gpu objects from reloaded assets.
+pub fn validated<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+
+    match device.pop_error_scope().block_on() {
+        Some(error) => Err(error),
+        None => Ok(created),
+    }
+}
+
+/// Offscreen texture the cameras draw into when running without a window.
+#[derive(Resource)]
+pub struct HeadlessTarget(pub wgpu::Texture);
+
+impl HeadlessTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        }))
+    }
+}
+
+/// Where the render plugin presents its frames, inserted by the application.
+#[derive(Resource, Clone)]
+pub enum RenderOutput {
+    Window(Arc<Window>),
+    Headless(PhysicalSize<u32>),
+}
+
+/// How the gpu is set up, fixed once [`RenderPlugin`] is built. Available as a
+/// resource afterwards.
+#[derive(Resource, Clone, Debug)]
+pub struct RenderSettings {
+    /// Backends wgpu may pick an adapter from
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Building fails if the adapter does not support all of them, e.g.
+    /// `PUSH_CONSTANTS`
+    pub features: wgpu::Features,
+    /// Building fails if the adapter does not reach them
+    pub limits: wgpu::Limits,
+    /// Window surface formats in order of preference. The first one the surface
+    /// supports is used, the first srgb format otherwise. Headless rendering
+    /// always uses [`HeadlessTarget::FORMAT`].
+    pub surface_formats: Vec<wgpu::TextureFormat>,
+    /// Samples per pixel, 1 disables multisampling. 4 is supported everywhere.
+    pub msaa_samples: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+            surface_formats: Vec::new(),
+            msaa_samples: 1,
+        }
+    }
+}
+
+/// Sets up wgpu for the [`RenderOutput`] and the render schedule's stages.
+/// Everything drawing depends on it, leave it out to run without a gpu.
+///
+/// Fails to build if no adapter matches the [`RenderSettings`].
+#[derive(Default)]
+pub struct RenderPlugin {
+    pub settings: RenderSettings,
+}
+
+impl Plugin for RenderPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        app.schedule_scope(RenderSchedule, |world, schedule| {
+            init_render_schedule(world, schedule, self.settings).block_on()
+        })
+    }
+}
+
+async fn init_render_schedule(
     world: &mut World,
-    window: &Window,
     schedule: &mut Schedule,
+    settings: RenderSettings,
 ) -> Result<(), anyhow::Error> {
+    let output = world
+        .get_resource::<RenderOutput>()
+        .cloned()
+        .ok_or(anyhow::anyhow!("No render output to draw into"))?;
+
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
+        backends: settings.backends,
         ..Default::default()
     });
 
-    let surface = unsafe { instance.create_surface(window) }?;
+    let surface = match &output {
+        RenderOutput::Window(window) => Some(unsafe { instance.create_surface(window.as_ref()) }?),
+        RenderOutput::Headless(_) => None,
+    };
 
-    let adapter = instance
+    let mut adapter = instance
         .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
+            power_preference: settings.power_preference,
+            compatible_surface: surface.as_ref(),
             force_fallback_adapter: false,
         })
-        .await
-        .ok_or(anyhow::anyhow!("Failed to find an appropriate adapter"))?;
+        .await;
+
+    if adapter.is_none() && surface.is_none() {
+        log::warn!("No hardware adapter found, falling back to software rendering");
+
+        adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: settings.power_preference,
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await;
+    }
+
+    let adapter = adapter.ok_or(anyhow::anyhow!("Failed to find an appropriate adapter"))?;
+
+    let missing_features = settings.features.difference(adapter.features());
+    if !missing_features.is_empty() {
+        anyhow::bail!(
+            "Adapter {} does not support the features {missing_features:?}",
+            adapter.get_info().name
+        );
+    }
 
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                ..Default::default()
+                label: None,
+                features: settings.features,
+                limits: settings.limits.clone(),
             },
             None,
         )
         .await?;
 
-    let surface_capabilities = surface.get_capabilities(&adapter);
+    let requested_present_mode = world
+        .get_resource_or_insert_with(WindowPresentMode::default)
+        .0;
+
+    let (surface_format, surface_usage, present_mode, size) = match (&surface, output) {
+        (Some(surface), RenderOutput::Window(window)) => {
+            let surface_capabilities = surface.get_capabilities(&adapter);
+
+            let surface_format = settings
+                .surface_formats
+                .iter()
+                .find(|f| surface_capabilities.formats.contains(f))
+                .or_else(|| surface_capabilities.formats.iter().find(|f| f.is_srgb()))
+                .ok_or(anyhow::anyhow!("No SRGB Surface"))?;
 
-    let surface_format = surface_capabilities
-        .formats
-        .iter()
-        .find(|f| f.is_srgb())
-        .ok_or(anyhow::anyhow!("No SRGB Surface"))?;
+            // allows reading back the window for screenshots where supported
+            let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_capabilities.usages & wgpu::TextureUsages::COPY_SRC);
 
-    let size = window.inner_size();
+            let present_mode =
+                supported_present_mode(requested_present_mode, &surface_capabilities.present_modes);
 
+            (*surface_format, usage, present_mode, window.inner_size())
+        }
+        (_, RenderOutput::Headless(size)) => (
+            HeadlessTarget::FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            requested_present_mode,
+            size,
+        ),
+        (None, RenderOutput::Window(_)) => unreachable!("window output always has a surface"),
+    };
+
+    // other targets are assumed to support the same sample counts
+    let msaa_supported = adapter
+        .get_texture_format_features(surface_format)
+        .flags
+        .sample_count_supported(settings.msaa_samples);
+    if !msaa_supported {
+        anyhow::bail!(
+            "{} MSAA samples are not supported for {surface_format:?}",
+            settings.msaa_samples
+        );
+    }
+
+    // in headless mode this only describes the offscreen target and is never
+    // used to configure a surface
     let config = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: *surface_format,
+        usage: surface_usage,
+        format: surface_format,
         width: size.width,
         height: size.height,
-        present_mode: wgpu::PresentMode::Fifo,
+        present_mode,
         alpha_mode: wgpu::CompositeAlphaMode::Opaque,
         view_formats: vec![],
     };
@@ -100,14 +338,24 @@ pub async fn init_render_schedule(
             }],
         });
 
+    match surface {
+        Some(surface) => world.insert_resource(WgpuSurface(surface)),
+        None => world.insert_resource(HeadlessTarget::new(&device, size.width, size.height)),
+    }
+
     world.insert_resource(WgpuAdapter(adapter));
-    world.insert_resource(WgpuSurface(surface));
     world.insert_resource(WgpuDevice(device));
     world.insert_resource(WgpuQueue(queue));
     world.insert_resource(WgpuConfig(config));
     world.insert_resource(CameraBindGroupLayout(camera_bind_group_layout));
+    world.insert_resource(GpuImages::default());
+    init_asset::<Image>(world, schedule);
+    init_asset::<Shader>(world, schedule);
+    world.insert_resource(SurfaceFrame::default());
+    world.insert_resource(SortedCameras::default());
+    world.insert_resource(MultisampledTargets::default());
+    world.insert_resource(settings);
 
-    world.insert_resource(Events::<ResizeEvent>::default());
     world.insert_resource(Events::<CommandBufferFinishedEvent>::default());
 
     // define order
@@ -121,31 +369,100 @@ pub async fn init_render_schedule(
     );
 
     schedule.add_systems((
-        (reconfigure_device_on_resize_system, prepare_render_system)
+        (
+            reconfigure_device_on_resize_system,
+            apply_present_mode_system,
+            prepare_images_system,
+            acquire_surface_frame_system,
+            prepare_render_system,
+        )
             .chain()
             .in_set(RenderStage::Prepare),
-        flush_render_system.in_set(RenderStage::Flush),
+        (flush_render_system, present_render_system)
+            .chain()
+            .in_set(RenderStage::Flush),
     ));
 
     Ok(())
 }
 
-fn prepare_render_system(
-    device: Res<WgpuDevice>,
-    surface: Res<WgpuSurface>,
-    camera_bind_group_layout: Res<CameraBindGroupLayout>,
-    mut cameras: Query<&mut Camera>,
-    queue: Res<WgpuQueue>,
+fn acquire_surface_frame_system(
+    surface: Option<Res<WgpuSurface>>,
+    mut surface_frame: ResMut<SurfaceFrame>,
 ) {
-    for mut camera in cameras.iter_mut() {
+    if let Some(surface) = surface {
         let output = surface
             .0
             .get_current_texture()
             .expect("cant get surface to draw on");
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        surface_frame.0 = Some(output);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_render_system(
+    device: Res<WgpuDevice>,
+    surface_frame: Res<SurfaceFrame>,
+    headless_target: Option<Res<HeadlessTarget>>,
+    low_resolution_target: Option<Res<LowResolutionTarget>>,
+    images: Res<GpuImages>,
+    config: Res<WgpuConfig>,
+    camera_bind_group_layout: Res<CameraBindGroupLayout>,
+    mut cameras: Query<(Entity, &mut Camera, Option<&GlobalTransform>)>,
+    mut sorted_cameras: ResMut<SortedCameras>,
+    queue: Res<WgpuQueue>,
+    settings: Res<RenderSettings>,
+    mut multisampled_targets: ResMut<MultisampledTargets>,
+    mut ignored_clears: Local<HashSet<Entity>>,
+) {
+    for (_, mut camera, global_transform) in cameras.iter_mut() {
+        let (view, format, target_size) = match camera.target {
+            RenderTarget::Image(handle) => {
+                let Some(image) = images.get(handle) else {
+                    log::warn!("Camera renders into missing image {handle:?}, skipping");
+                    camera.view = None;
+                    continue;
+                };
+
+                let size = glam::UVec2::new(image.size.width, image.size.height);
+                (image.create_view(), image.format, size)
+            }
+            RenderTarget::Window if low_resolution_target.is_some() => {
+                let target = low_resolution_target.as_ref().unwrap();
+                (target.create_view(), target.texture.format(), target.size)
+            }
+            RenderTarget::Window => {
+                let texture = match (&surface_frame.0, &headless_target) {
+                    (Some(output), _) => &output.texture,
+                    (None, Some(target)) => &target.0,
+                    (None, None) => {
+                        panic!("no render output, neither a surface nor a headless target")
+                    }
+                };
+
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let size = glam::UVec2::new(config.0.width, config.0.height);
+                (view, config.0.format, size)
+            }
+        };
+
+        camera.update_viewport(target_size);
+
+        if camera.physical_viewport.is_none() {
+            camera.view = None;
+            continue;
+        }
+
+        camera.multisampled_view = (settings.msaa_samples > 1).then(|| {
+            multisampled_targets.view(
+                &device.0,
+                camera.target,
+                format,
+                target_size,
+                settings.msaa_samples,
+            )
+        });
 
         if camera.uniform.is_none() {
             let uniform = device.0.create_buffer(&wgpu::BufferDescriptor {
@@ -158,13 +475,18 @@ fn prepare_render_system(
             camera.uniform = Some(uniform);
         }
 
+        if let Some(global_transform) = global_transform {
+            camera.eye = global_transform.translation();
+            camera.direction = global_transform.forward();
+        }
+
         // set uniform
-        camera.projection = camera.get_projection_matrix();
+        camera.view_projection = camera.get_projection_matrix();
 
         let uniform = camera.uniform.as_ref().unwrap();
         queue
             .0
-            .write_buffer(uniform, 0, bytemuck::bytes_of(&camera.projection));
+            .write_buffer(uniform, 0, bytemuck::bytes_of(&camera.view_projection));
 
         if camera.bind_group.is_none() {
             let bind_group = device.0.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -179,63 +501,453 @@ fn prepare_render_system(
             camera.bind_group = Some(bind_group);
         }
 
-        camera.output = Some(output);
         camera.view = Some(view);
+        camera.format = Some(format);
+    }
+
+    // cameras rendering into images go first, so their output can be sampled
+    // by the cameras drawing to the window in the same frame
+    let mut prepared: Vec<_> = cameras
+        .iter_mut()
+        .filter(|(_, camera, _)| camera.view.is_some())
+        .map(|(entity, camera, _)| (entity, camera.into_inner()))
+        .collect();
+    prepared.sort_by_key(|(_, camera)| (camera.target == RenderTarget::Window, camera.order));
+
+    sorted_cameras.0 = prepared.iter().map(|(entity, _)| *entity).collect();
+    resolve_load_ops(prepared, &mut ignored_clears);
+}
+
+/// Picks how each camera loads its target, `cameras` in drawing order. A render
+/// pass clears the whole target, not just the viewport, so only the first
+/// camera on a target can clear it. Later cameras asking for a clear load
+/// instead, so split screen cameras don't wipe each other, and are warned about
+/// once, remembered in `ignored_clears`.
+fn resolve_load_ops<'a>(
+    cameras: impl IntoIterator<Item = (Entity, &'a mut Camera)>,
+    ignored_clears: &mut HashSet<Entity>,
+) {
+    let mut drawn_targets = HashSet::new();
+
+    for (entity, camera) in cameras {
+        let first_on_target = drawn_targets.insert(camera.target);
+
+        camera.load_op = match camera.clear {
+            ClearOp::Clear(color) if first_on_target => wgpu::LoadOp::Clear(color),
+            ClearOp::Clear(_) => {
+                if ignored_clears.insert(entity) {
+                    log::warn!(
+                        "Camera {entity:?} clears {:?}, which an earlier camera drew into, loading it instead",
+                        camera.target
+                    );
+                }
+
+                wgpu::LoadOp::Load
+            }
+            ClearOp::Load => wgpu::LoadOp::Load,
+            ClearOp::None if first_on_target => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            ClearOp::None => wgpu::LoadOp::Load,
+        };
+    }
+}
+
+/// Multisampled textures cameras draw into before resolving to their target.
+/// One per target, so cameras sharing a target draw on top of each other.
+#[derive(Resource, Default)]
+struct MultisampledTargets(HashMap<RenderTarget, wgpu::Texture>);
+
+impl MultisampledTargets {
+    /// Recreates the texture when the target was resized.
+    fn view(
+        &mut self,
+        device: &wgpu::Device,
+        target: RenderTarget,
+        format: wgpu::TextureFormat,
+        size: glam::UVec2,
+        samples: u32,
+    ) -> wgpu::TextureView {
+        let outdated = self.0.get(&target).is_none_or(|texture| {
+            texture.format() != format || texture.width() != size.x || texture.height() != size.y
+        });
+
+        if outdated {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Multisampled Render Target"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: samples,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            });
+
+            self.0.insert(target, texture);
+        }
+
+        self.0[&target].create_view(&wgpu::TextureViewDescriptor::default())
     }
 }
 
 #[derive(Event)]
 pub struct CommandBufferFinishedEvent(pub wgpu::CommandBuffer);
 
+/// The surface texture of the current frame. Acquired once per frame and
+/// shared by all cameras drawing to the window.
+#[derive(Resource, Default)]
+pub struct SurfaceFrame(pub Option<wgpu::SurfaceTexture>);
+
+/// Cameras that are ready to draw this frame, in the order they have to be drawn in.
+#[derive(Resource, Default)]
+pub struct SortedCameras(pub Vec<Entity>);
+
+/// What a camera draws into.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum RenderTarget {
+    /// The window surface, or the offscreen target when running headless
+    #[default]
+    Window,
+    /// An image created with [`GpuImages::create_render_target`], can be sampled
+    /// by sprites drawn from other cameras in the same frame
+    Image(Handle<Image>),
+}
+
+/// Region of the render target a camera draws into, in physical pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Viewport {
+    pub position: glam::UVec2,
+    pub size: glam::UVec2,
+}
+
+/// How a camera maps the world onto its viewport.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Projection {
+    /// Uses the camera's `fov`
+    #[default]
+    Perspective,
+    Orthographic(ScalingMode),
+}
+
+impl Projection {
+    /// Orthographic projection showing `height` world units vertically.
+    pub fn orthographic(height: f32) -> Self {
+        Self::Orthographic(ScalingMode::FixedVertical(height))
+    }
+}
+
+/// How many world units an orthographic camera shows.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScalingMode {
+    /// Shows this many world units vertically, the width follows the aspect ratio
+    FixedVertical(f32),
+    /// Shows one world unit per this many physical pixels, so resizing the
+    /// window shows more of the world instead of scaling it
+    WindowSize(f32),
+    /// Always shows exactly this many world units, the viewport is shrunk to
+    /// keep the aspect ratio, leaving bars at the sides or top and bottom
+    FixedResolution { width: f32, height: f32 },
+}
+
+/// How a camera treats the contents of its target before drawing. Clearing
+/// affects the whole target, not just the viewport, so only the first camera
+/// drawing into a target clears it, later ones load it instead.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ClearOp {
+    /// Clear the target to the given color, if this is the first camera drawing
+    /// into it this frame
+    Clear(wgpu::Color),
+    /// Keep what earlier cameras drew into the target
+    Load,
+    /// Clear to transparent if this is the first camera drawing into the target
+    /// this frame, otherwise keep its contents
+    #[default]
+    None,
+}
+
+/// Half line in world space, e.g. from the camera through the cursor.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Ray {
+    pub origin: glam::Vec3,
+    /// Normalized
+    pub direction: glam::Vec3,
+}
+
+impl Ray {
+    pub fn point_at(&self, distance: f32) -> glam::Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to the plane through `point` facing `normal`,
+    /// None if the ray is parallel to it or points away.
+    pub fn intersect_plane(&self, point: glam::Vec3, normal: glam::Vec3) -> Option<f32> {
+        let denominator = normal.dot(self.direction);
+        if denominator.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let distance = (point - self.origin).dot(normal) / denominator;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Camera {
     // public
+    pub target: RenderTarget,
+    /// Defaults to the whole target
+    pub viewport: Option<Viewport>,
+    /// Cameras sharing a target are drawn in ascending order
+    pub order: isize,
+    pub clear: ClearOp,
+    pub projection: Projection,
+    /// Follows the entity's [`GlobalTransform`] if it has one
     pub eye: glam::Vec3,
+    /// Follows the entity's [`GlobalTransform`] if it has one, looking along its -z axis
     pub direction: glam::Vec3,
+    /// Width divided by height of the viewport, updated before every frame
     pub aspect: f32,
+    /// Vertical field of view of [`Projection::Perspective`]
     pub fov: f32,
     pub clip_near: f32,
     pub clip_far: f32,
 
     // render internals
-    pub output: Option<wgpu::SurfaceTexture>,
     pub view: Option<wgpu::TextureView>,
-    pub projection: glam::Mat4,
+    /// Drawn into instead of `view` when MSAA is on, has to be resolved into `view`
+    pub multisampled_view: Option<wgpu::TextureView>,
+    pub format: Option<wgpu::TextureFormat>,
+    pub load_op: wgpu::LoadOp<wgpu::Color>,
+    /// The viewport in physical pixels, after letterboxing. Follows the size of
+    /// the target, for window cameras the surface size in [`WgpuConfig`]. None
+    /// if the viewport lies outside the target, the camera is skipped then
+    pub physical_viewport: Option<Viewport>,
+    pub view_projection: glam::Mat4,
     pub uniform: Option<wgpu::Buffer>,
     pub bind_group: Option<wgpu::BindGroup>,
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            target: RenderTarget::Window,
+            viewport: None,
+            order: 0,
+            clear: ClearOp::None,
+            projection: Projection::Perspective,
+            eye: (0.0, 0.0, 2.0).into(),
+            direction: glam::Vec3::NEG_Z,
+            aspect: 1.0,
+            fov: 90.0,
+            clip_near: 0.1,
+            clip_far: 100.0,
+
+            view: None,
+            multisampled_view: None,
+            format: None,
+            load_op: wgpu::LoadOp::Load,
+            physical_viewport: None,
+            view_projection: glam::Mat4::IDENTITY,
+            uniform: None,
+            bind_group: None,
+        }
+    }
+}
+
 impl Camera {
+    /// Camera with an orthographic projection, for 2D scenes.
+    pub fn orthographic(scaling: ScalingMode) -> Self {
+        Self {
+            projection: Projection::Orthographic(scaling),
+            ..Default::default()
+        }
+    }
+
+    /// Computes the physical viewport and aspect ratio for a target of `target_size` pixels.
+    /// The viewport is clamped to the target, which can shrink below it.
+    pub fn update_viewport(&mut self, target_size: glam::UVec2) {
+        let mut viewport = self.viewport.unwrap_or(Viewport {
+            position: glam::UVec2::ZERO,
+            size: target_size,
+        });
+
+        viewport.position = viewport.position.min(target_size);
+        viewport.size = viewport.size.min(target_size - viewport.position);
+
+        if viewport.size.cmpeq(glam::UVec2::ZERO).any() {
+            self.physical_viewport = None;
+            return;
+        }
+
+        if let Projection::Orthographic(ScalingMode::FixedResolution { width, height }) =
+            self.projection
+        {
+            let available = viewport.size.as_vec2();
+            let scale = (available.x / width).min(available.y / height);
+            let size = (glam::Vec2::new(width, height) * scale)
+                .round()
+                .as_uvec2()
+                .max(glam::UVec2::ONE);
+
+            viewport = Viewport {
+                position: viewport.position + (viewport.size.saturating_sub(size)) / 2,
+                size,
+            };
+        }
+
+        self.aspect = viewport.size.x as f32 / viewport.size.y.max(1) as f32;
+        self.physical_viewport = Some(viewport);
+    }
+
+    /// Ray through a pixel of the render target, with the origin on the near
+    /// plane. For orthographic cameras all rays share the camera's direction.
+    ///
+    /// Positions are physical pixels from the top left of the target, the window
+    /// for window cameras. None before the camera was first prepared or outside
+    /// its viewport, which ends before `position + size`.
+    pub fn screen_to_world_ray(&self, position: glam::Vec2) -> Option<Ray> {
+        let viewport = self.physical_viewport?;
+        let relative = (position - viewport.position.as_vec2()) / viewport.size.as_vec2();
+
+        if relative.cmplt(glam::Vec2::ZERO).any() || relative.cmpge(glam::Vec2::ONE).any() {
+            return None;
+        }
+
+        let ndc = glam::Vec2::new(relative.x * 2.0 - 1.0, 1.0 - relative.y * 2.0);
+        let inverse = self.get_projection_matrix().inverse();
+
+        // wgpu depth goes from 0 at the near to 1 at the far plane
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+
+        Some(Ray {
+            origin: near,
+            direction: (far - near).try_normalize()?,
+        })
+    }
+
+    /// Point on the z = 0 plane under a pixel of the render target, where 2D
+    /// sprites usually live.
+    pub fn screen_to_world_2d(&self, position: glam::Vec2) -> Option<glam::Vec2> {
+        let ray = self.screen_to_world_ray(position)?;
+        let distance = ray.intersect_plane(glam::Vec3::ZERO, glam::Vec3::Z)?;
+
+        Some(ray.point_at(distance).truncate())
+    }
+
+    /// Pixel of the render target showing `point`, from the top left. None if
+    /// the point is behind the camera or outside the clip planes, points
+    /// outside the viewport return positions beyond its edges.
+    pub fn world_to_screen(&self, point: glam::Vec3) -> Option<glam::Vec2> {
+        let viewport = self.physical_viewport?;
+        let clip = self.get_projection_matrix() * point.extend(1.0);
+
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+        if !(0.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+
+        let relative = glam::Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) / 2.0;
+        Some(viewport.position.as_vec2() + relative * viewport.size.as_vec2())
+    }
+
     fn get_projection_matrix(&self) -> glam::Mat4 {
         let view = glam::Mat4::look_to_rh(self.eye, self.direction, glam::Vec3::Y);
-        let projection =
-            glam::Mat4::perspective_rh(self.fov, self.aspect, self.clip_near, self.clip_far);
+
+        let half_size = match self.projection {
+            Projection::Perspective => {
+                let projection = glam::Mat4::perspective_rh(
+                    self.fov,
+                    self.aspect,
+                    self.clip_near,
+                    self.clip_far,
+                );
+
+                return projection * view;
+            }
+            Projection::Orthographic(ScalingMode::FixedVertical(height)) => {
+                glam::Vec2::new(height * self.aspect, height) / 2.0
+            }
+            Projection::Orthographic(ScalingMode::WindowSize(pixels_per_unit)) => {
+                let size = self
+                    .physical_viewport
+                    .map_or(glam::Vec2::ONE, |viewport| viewport.size.as_vec2());
+
+                size / pixels_per_unit / 2.0
+            }
+            Projection::Orthographic(ScalingMode::FixedResolution { width, height }) => {
+                glam::Vec2::new(width, height) / 2.0
+            }
+        };
+
+        let projection = glam::Mat4::orthographic_rh(
+            -half_size.x,
+            half_size.x,
+            -half_size.y,
+            half_size.y,
+            self.clip_near,
+            self.clip_far,
+        );
 
         projection * view
     }
 }
 
-fn flush_render_system(
+pub fn flush_render_system(
     queue: Res<WgpuQueue>,
     mut command_buffers: ResMut<Events<CommandBufferFinishedEvent>>,
-    mut cameras: Query<&mut Camera>,
 ) {
     queue
         .0
         .submit(command_buffers.drain().map(|buffer| buffer.0));
+}
 
-    for mut camera in cameras.iter_mut() {
-        if let Some(output) = camera.output.take() {
-            output.present()
-        }
+pub fn present_render_system(mut surface_frame: ResMut<SurfaceFrame>) {
+    if let Some(output) = surface_frame.0.take() {
+        output.present()
+    }
+}
+
+fn apply_present_mode_system(
+    present_mode: Res<WindowPresentMode>,
+    adapter: Res<WgpuAdapter>,
+    device: Res<WgpuDevice>,
+    surface: Option<Res<WgpuSurface>>,
+    mut config: ResMut<WgpuConfig>,
+) {
+    if !present_mode.is_changed() {
+        return;
+    }
+
+    let Some(surface) = surface else {
+        return;
+    };
+
+    let supported = surface.0.get_capabilities(&adapter.0).present_modes;
+    let present_mode = supported_present_mode(present_mode.0, &supported);
+
+    if config.0.present_mode != present_mode {
+        log::info!("Switching present mode to {present_mode:?}");
+
+        config.0.present_mode = present_mode;
+        surface.0.configure(&device.0, &config.0);
     }
 }
 
 fn reconfigure_device_on_resize_system(
     mut resize_event: EventReader<ResizeEvent>,
     device: Res<WgpuDevice>,
-    surface: Res<WgpuSurface>,
+    surface: Option<Res<WgpuSurface>>,
+    mut headless_target: Option<ResMut<HeadlessTarget>>,
     mut config: ResMut<WgpuConfig>,
+    low_resolution_target: Option<Res<LowResolutionTarget>>,
     mut cameras: Query<&mut Camera>,
 ) {
     for e in resize_event.read() {
@@ -243,11 +955,146 @@ fn reconfigure_device_on_resize_system(
         let new_size = e.0;
         config.0.width = new_size.width;
         config.0.height = new_size.height;
-        surface.0.configure(&device.0, &config.0);
 
-        let aspect = new_size.width as f32 / new_size.height as f32;
-        for mut camera in cameras.iter_mut() {
-            camera.aspect = aspect;
+        if let Some(surface) = &surface {
+            surface.0.configure(&device.0, &config.0);
+        }
+
+        if let Some(target) = &mut headless_target {
+            **target = HeadlessTarget::new(&device.0, new_size.width, new_size.height);
+        }
+
+        // letterboxing and the aspect ratio depend on the window size, unless
+        // window cameras draw into a low resolution target
+        let target_size = match &low_resolution_target {
+            Some(target) => target.size,
+            None => glam::UVec2::new(new_size.width, new_size.height),
+        };
+        for mut camera in cameras
+            .iter_mut()
+            .filter(|camera| camera.target == RenderTarget::Window)
+        {
+            camera.update_viewport(target_size);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+
+    use super::*;
+
+    fn camera_with_viewport(position: UVec2, size: UVec2) -> Camera {
+        Camera {
+            viewport: Some(Viewport { position, size }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn viewport_is_clamped_to_a_shrunk_target() {
+        let mut camera = camera_with_viewport(UVec2::new(100, 50), UVec2::new(200, 200));
+        camera.update_viewport(UVec2::new(250, 150));
+
+        assert_eq!(
+            camera.physical_viewport,
+            Some(Viewport {
+                position: UVec2::new(100, 50),
+                size: UVec2::new(150, 100),
+            })
+        );
+    }
+
+    fn assert_round_trip(camera: &Camera, position: glam::Vec2) {
+        let world = camera
+            .screen_to_world_2d(position)
+            .unwrap_or_else(|| panic!("{position} has no world position"));
+        let screen = camera.world_to_screen(world.extend(0.0)).unwrap();
+
+        assert!(
+            screen.abs_diff_eq(position, 1e-2),
+            "{position} went to {world} and back to {screen}"
+        );
+    }
+
+    #[test]
+    fn orthographic_screen_positions_round_trip() {
+        let mut camera = Camera::orthographic(ScalingMode::FixedVertical(2.0));
+        camera.update_viewport(UVec2::new(200, 100));
+
+        for position in [(0.0, 0.0), (100.0, 50.0), (37.5, 80.25), (199.5, 99.5)] {
+            assert_round_trip(&camera, position.into());
+        }
+
+        // the center of the screen looks at the camera's position
+        let center = camera.screen_to_world_2d(glam::Vec2::new(100.0, 50.0));
+        assert_eq!(center, Some(glam::Vec2::ZERO));
+    }
+
+    #[test]
+    fn perspective_screen_positions_round_trip_in_an_offset_viewport() {
+        let mut camera = Camera {
+            eye: glam::Vec3::new(1.0, 2.0, 5.0),
+            direction: glam::Vec3::new(-0.2, -0.3, -1.0).normalize(),
+            fov: 1.0,
+            ..camera_with_viewport(UVec2::new(50, 20), UVec2::new(200, 150))
+        };
+        camera.update_viewport(UVec2::new(400, 300));
+
+        for position in [(50.0, 20.0), (150.0, 95.0), (73.25, 160.5), (249.5, 169.5)] {
+            assert_round_trip(&camera, position.into());
+        }
+    }
+
+    #[test]
+    fn positions_outside_the_viewport_have_no_ray() {
+        let mut camera = camera_with_viewport(UVec2::new(50, 20), UVec2::new(200, 150));
+        assert_eq!(
+            camera.screen_to_world_ray(glam::Vec2::new(100.0, 100.0)),
+            None
+        );
+
+        camera.update_viewport(UVec2::new(400, 300));
+
+        for position in [(49.5, 100.0), (100.0, 19.5), (250.0, 100.0), (100.0, 170.0)] {
+            let position = position.into();
+            assert_eq!(camera.screen_to_world_ray(position), None, "{position}");
+            assert_eq!(camera.screen_to_world_2d(position), None, "{position}");
+        }
+
+        assert!(camera
+            .screen_to_world_ray(glam::Vec2::new(249.9, 169.9))
+            .is_some());
+    }
+
+    #[test]
+    fn only_the_first_camera_on_a_target_clears_it() {
+        let red = wgpu::Color::RED;
+        let blue = wgpu::Color::BLUE;
+
+        let mut left = camera_with_viewport(UVec2::ZERO, UVec2::new(100, 100));
+        left.clear = ClearOp::Clear(red);
+        let mut right = camera_with_viewport(UVec2::new(100, 0), UVec2::new(100, 100));
+        right.clear = ClearOp::Clear(blue);
+
+        let mut ignored_clears = HashSet::new();
+        let (first, second) = (Entity::from_raw(0), Entity::from_raw(1));
+        resolve_load_ops(
+            [(first, &mut left), (second, &mut right)],
+            &mut ignored_clears,
+        );
+
+        assert_eq!(left.load_op, wgpu::LoadOp::Clear(red));
+        assert_eq!(right.load_op, wgpu::LoadOp::Load);
+        assert_eq!(ignored_clears, HashSet::from([second]));
+    }
+
+    #[test]
+    fn viewport_outside_the_target_is_skipped() {
+        let mut camera = camera_with_viewport(UVec2::new(300, 0), UVec2::new(100, 100));
+        camera.update_viewport(UVec2::new(250, 150));
+
+        assert_eq!(camera.physical_viewport, None);
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use bevy_ecs::{
+    entity::Entity,
+    event::{Event, Events},
+    schedule::IntoSystemConfigs as _,
+    world::World,
+};
+
+use crate::application::RenderSchedule;
+
+use super::{
+    images::GpuImages,
+    pixel_perfect::LowResolutionTarget,
+    rendering::{
+        flush_render_system, present_render_system, Camera, HeadlessTarget, RenderPlugin,
+        RenderStage, RenderTarget, SurfaceFrame, WgpuDevice, WgpuQueue,
+    },
+    App, Plugin, PluginId,
+};
+
+/// Writes the frame of `camera` to a png at `path` once it has been rendered.
+#[derive(Event)]
+pub struct Screenshot {
+    pub camera: Entity,
+    pub path: PathBuf,
+}
+
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.insert_resource(Events::<Screenshot>::default());
+
+        // after submitting, but while the surface texture is still around
+        app.add_systems(
+            RenderSchedule,
+            take_screenshots_system
+                .in_set(RenderStage::Flush)
+                .after(flush_render_system)
+                .before(present_render_system),
+        );
+
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![PluginId::of::<RenderPlugin>()]
+    }
+}
+
+fn take_screenshots_system(world: &mut World) {
+    let screenshots: Vec<_> = world.resource_mut::<Events<Screenshot>>().drain().collect();
+
+    for screenshot in screenshots {
+        let result = capture_camera(world, screenshot.camera)
+            .and_then(|image| Ok(image.save(&screenshot.path)?));
+
+        match result {
+            Ok(()) => log::info!("Saved screenshot to {}", screenshot.path.display()),
+            Err(error) => log::error!(
+                "Failed to save screenshot to {}: {error}",
+                screenshot.path.display()
+            ),
+        }
+    }
+}
+
+/// Reads back the last frame `camera` rendered, cropped to its viewport.
+///
+/// Cameras drawing to the window can only be captured between submitting and
+/// presenting the frame, headless and image targets can be captured any time.
+pub fn capture_camera(world: &World, camera: Entity) -> Result<image::RgbaImage, anyhow::Error> {
+    let camera = world
+        .get::<Camera>(camera)
+        .ok_or(anyhow::anyhow!("{camera:?} is not a camera"))?;
+
+    let texture = match camera.target {
+        RenderTarget::Image(handle) => {
+            &world
+                .resource::<GpuImages>()
+                .get(handle)
+                .ok_or(anyhow::anyhow!(
+                    "Camera renders into missing image {handle:?}"
+                ))?
+                .texture
+        }
+        RenderTarget::Window if world.contains_resource::<LowResolutionTarget>() => {
+            &world.resource::<LowResolutionTarget>().texture
+        }
+        RenderTarget::Window => {
+            let surface_frame = world
+                .get_resource::<SurfaceFrame>()
+                .and_then(|frame| frame.0.as_ref());
+
+            match (surface_frame, world.get_resource::<HeadlessTarget>()) {
+                (Some(output), _) => &output.texture,
+                (None, Some(target)) => &target.0,
+                (None, None) => anyhow::bail!("Window frame was already presented"),
+            }
+        }
+    };
+
+    let (origin, size) = match camera.physical_viewport {
+        Some(viewport) => (viewport.position, viewport.size),
+        None => (
+            glam::UVec2::ZERO,
+            glam::UVec2::new(texture.width(), texture.height()),
+        ),
+    };
+
+    read_texture(
+        &world.resource::<WgpuDevice>().0,
+        &world.resource::<WgpuQueue>().0,
+        texture,
+        origin,
+        size,
+    )
+}
+
+/// Copies a region of an 8 bit rgba or bgra texture back to the cpu. Blocks until
+/// the gpu is done with all submitted work.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    origin: glam::UVec2,
+    size: glam::UVec2,
+) -> Result<image::RgbaImage, anyhow::Error> {
+    let swizzle = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        format => anyhow::bail!("Reading back {format:?} textures is not supported"),
+    };
+
+    // not every surface can be copied from, see the surface usage in rendering
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+        anyhow::bail!("Texture can not be read back, it was created without COPY_SRC");
+    }
+
+    let unpadded_bytes_per_row = size.x * 4;
+    let padded_bytes_per_row =
+        unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Texture Readback Buffer"),
+        size: (padded_bytes_per_row * size.y) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Texture Readback"),
+    });
+
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: origin.x,
+                y: origin.y,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size.y),
+            },
+        },
+        wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * size.y) as usize);
+    for row in slice
+        .get_mapped_range()
+        .chunks_exact(padded_bytes_per_row as usize)
+    {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+
+    buffer.unmap();
+
+    if swizzle {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_raw(size.x, size.y, pixels)
+        .ok_or(anyhow::anyhow!("Readback buffer has the wrong size"))
+}
@@ -1,36 +0,0 @@
-struct CameraUniform {
-    projection: mat4x4<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-}
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) uv: vec2<f32>,
-}
-
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
-
-@vertex
-fn vertex_main(vertex: VertexInput) -> VertexOutput {
-    var output: VertexOutput;
-
-    output.clip_position = camera.projection * vec4<f32>(vertex.position, 1.0);
-    output.uv = vertex.uv;
-
-    return output;
-}
-
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
-
-@fragment
-fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.uv);
-}
@@ -1,16 +1,27 @@
+use std::{collections::HashMap, ops::Range};
+
 use bevy_ecs::{
-    event::EventWriter,
+    component::Component,
+    event::{EventReader, EventWriter},
     schedule::IntoSystemConfigs as _,
-    system::{Query, Res, Resource},
+    system::{Query, Res, ResMut, Resource},
 };
-use image::GenericImageView;
-use wgpu::include_wgsl;
+use glam::{Vec2, Vec4};
+use wgpu::{include_wgsl, util::DeviceExt as _};
+
+use crate::application::RenderSchedule;
 
 use super::{
+    assets::{init_asset, AssetEvent, AssetPlugin, AssetServer, Assets, Handle},
+    images::{GpuImages, Image},
     rendering::{
-        Camera, CommandBufferFinishedEvent, RenderStage, WgpuConfig, WgpuDevice, WgpuQueue,
+        load_builtin_shader, validated, Camera, CommandBufferFinishedEvent, RenderPlugin,
+        RenderSettings, RenderStage, RenderTarget, Shader, SortedCameras, WgpuConfig, WgpuDevice,
+        WgpuQueue,
     },
-    Plugin,
+    texture_atlas::{update_atlas_sprites_system, TextureAtlas},
+    transform::{GlobalTransform, TransformPlugin},
+    App, Plugin, PluginId,
 };
 
 #[repr(C)]
@@ -22,10 +33,10 @@ pub struct Vertex {
 
 #[rustfmt::skip]
 const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.25, 0.25, 0.0], texture_coordinates: [0.0, 0.0] },
-    Vertex { position: [-0.25, -0.25, 0.0], texture_coordinates: [0.0, 1.0] },
-    Vertex { position: [0.25, 0.25, 0.0], texture_coordinates: [1.0, 0.0] },
-    Vertex { position: [0.25, -0.25, 0.0], texture_coordinates: [1.0, 1.0] },
+    Vertex { position: [-0.5, 0.5, 0.0], texture_coordinates: [0.0, 0.0] },
+    Vertex { position: [-0.5, -0.5, 0.0], texture_coordinates: [0.0, 1.0] },
+    Vertex { position: [0.5, 0.5, 0.0], texture_coordinates: [1.0, 0.0] },
+    Vertex { position: [0.5, -0.5, 0.0], texture_coordinates: [1.0, 1.0] },
 ];
 
 impl Vertex {
@@ -42,17 +53,142 @@ impl Vertex {
     }
 }
 
+/// Per sprite data, the unit quad in [`VERTICES`] is transformed by `model`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instance {
+    model: [[f32; 4]; 4],
+    /// min and max texture coordinates
+    uv_rect: [f32; 4],
+    tint: [f32; 4],
+}
+
+impl Instance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        2 => Float32x4, 3 => Float32x4, 4 => Float32x4, 5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+    ];
+
+    #[inline]
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Rectangle in texture pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Which point of the sprite sits at its transform's translation.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Anchor {
+    #[default]
+    Center,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+    CenterLeft,
+    CenterRight,
+    TopLeft,
+    TopCenter,
+    TopRight,
+    /// Offset from the center, where (-0.5, -0.5) is the bottom left corner
+    Custom(Vec2),
+}
+
+impl Anchor {
+    pub fn as_vec(&self) -> Vec2 {
+        match self {
+            Anchor::Center => Vec2::ZERO,
+            Anchor::BottomLeft => Vec2::new(-0.5, -0.5),
+            Anchor::BottomCenter => Vec2::new(0.0, -0.5),
+            Anchor::BottomRight => Vec2::new(0.5, -0.5),
+            Anchor::CenterLeft => Vec2::new(-0.5, 0.0),
+            Anchor::CenterRight => Vec2::new(0.5, 0.0),
+            Anchor::TopLeft => Vec2::new(-0.5, 0.5),
+            Anchor::TopCenter => Vec2::new(0.0, 0.5),
+            Anchor::TopRight => Vec2::new(0.5, 0.5),
+            Anchor::Custom(offset) => *offset,
+        }
+    }
+}
+
+/// A textured quad drawn at the entity's [`GlobalTransform`].
+#[derive(Component, Clone, Debug)]
+pub struct Sprite {
+    pub texture: Handle<Image>,
+    /// Size in world units, before the transform's scale is applied
+    pub size: Vec2,
+    pub anchor: Anchor,
+    /// Multiplied with the texture color, linear rgba
+    pub tint: Vec4,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Region of the texture to show, defaults to the whole texture
+    pub source_rect: Option<Rect>,
+}
+
+impl Sprite {
+    pub fn new(texture: Handle<Image>) -> Self {
+        Self {
+            texture,
+            size: Vec2::ONE,
+            anchor: Anchor::Center,
+            tint: Vec4::ONE,
+            flip_x: false,
+            flip_y: false,
+            source_rect: None,
+        }
+    }
+
+    /// A sprite without texture, for use together with a
+    /// [`TextureAtlasSprite`](super::texture_atlas::TextureAtlasSprite).
+    pub fn from_atlas() -> Self {
+        Self::new(Handle::default())
+    }
+
+    fn instance(&self, transform: &GlobalTransform, texture_size: Vec2) -> Instance {
+        let model = transform.0
+            * glam::Mat4::from_scale(self.size.extend(1.0))
+            * glam::Mat4::from_translation(-self.anchor.as_vec().extend(0.0));
+
+        let source = self.source_rect.unwrap_or(Rect {
+            min: Vec2::ZERO,
+            max: texture_size,
+        });
+
+        let (mut uv_min, mut uv_max) = (source.min / texture_size, source.max / texture_size);
+        if self.flip_x {
+            std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+        }
+        if self.flip_y {
+            std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+        }
+
+        Instance {
+            model: model.to_cols_array_2d(),
+            uv_rect: [uv_min.x, uv_min.y, uv_max.x, uv_max.y],
+            tint: self.tint.to_array(),
+        }
+    }
+}
+
 pub struct SpritePlugin;
 
 impl Plugin for SpritePlugin {
-    fn build(
-        self,
-        world: &mut bevy_ecs::world::World,
-        schedule: &mut bevy_ecs::schedule::Schedule,
-    ) {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
         let device = &world.resource::<WgpuDevice>().0;
         let config = &world.resource::<WgpuConfig>().0;
-        let queue = &world.resource::<WgpuQueue>().0;
+        let sample_count = world.resource::<RenderSettings>().msaa_samples;
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -77,7 +213,7 @@ impl Plugin for SpritePlugin {
                 ],
             });
 
-        let shader = device.create_shader_module(include_wgsl!("sprite.wgsl"));
+        let shader = device.create_shader_module(include_wgsl!("../../assets/shaders/sprite.wgsl"));
         let camera_bind_group_layout = &world
             .resource::<super::rendering::CameraBindGroupLayout>()
             .0;
@@ -88,66 +224,220 @@ impl Plugin for SpritePlugin {
             push_constant_ranges: &[],
         });
 
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            config.format,
+            sample_count,
+        );
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Sprite Vertex Buffer"),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            size: std::mem::size_of_val(VERTICES) as u64,
-            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::VERTEX,
+            contents: bytemuck::cast_slice(VERTICES),
         });
 
-        let bind_group = {
-            let bytes = include_bytes!("../../happy-tree.png");
-            let image = image::load_from_memory(bytes).expect("valid png");
-            let rgba = image.to_rgba8();
-
-            let dimensions = image.dimensions();
-
-            let texture_size = wgpu::Extent3d {
-                width: dimensions.0,
-                height: dimensions.1,
-                depth_or_array_layers: 1,
-            };
-
-            let texture = device.create_texture(&wgpu::TextureDescriptor {
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("../../happy-tree.png"),
-                view_formats: &[],
-            });
+        let pipelines = HashMap::from([(config.format, pipeline)]);
 
-            queue.write_texture(
-                wgpu::ImageCopyTextureBase {
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                &rgba,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * dimensions.0),
-                    rows_per_image: Some(dimensions.1),
-                },
-                texture_size,
-            );
+        let shader_handle = load_builtin_shader(world.resource::<AssetServer>(), SHADER_PATH);
+
+        world.insert_resource(SpritePluginContext {
+            pipeline_layout,
+            shader,
+            shader_handle,
+            pipelines,
+            sample_count,
+            texture_bind_group_layout,
+            sampler,
+            texture_bind_groups: HashMap::new(),
+            vertex_buffer,
+            instance_buffer: None,
+        });
+
+        app.schedule_scope(RenderSchedule, init_asset::<TextureAtlas>);
+
+        app.add_systems(
+            RenderSchedule,
+            (
+                (
+                    reload_sprite_shader_system,
+                    invalidate_sprite_textures_system,
+                    update_atlas_sprites_system,
+                )
+                    .in_set(RenderStage::Prepare),
+                draw_sprites_system.in_set(RenderStage::Render),
+            ),
+        );
+
+        Ok(())
+    }
+
+    /// The shader is tracked by the asset server, sprites are placed by their
+    /// [`GlobalTransform`].
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![
+            PluginId::of::<RenderPlugin>(),
+            PluginId::of::<AssetPlugin>(),
+            PluginId::of::<TransformPlugin>(),
+        ]
+    }
+}
+
+/// Where `sprite.wgsl` is loaded from, relative to the asset root.
+const SHADER_PATH: &str = "shaders/sprite.wgsl";
+
+/// Rebuilds the pipelines when `sprite.wgsl` changed. Broken shaders are
+/// logged and the previous pipelines stay in use.
+fn reload_sprite_shader_system(
+    mut shader_events: EventReader<AssetEvent<Shader>>,
+    shaders: Res<Assets<Shader>>,
+    device: Res<WgpuDevice>,
+    mut sprite_plugin_context: ResMut<SpritePluginContext>,
+) {
+    let context = &mut *sprite_plugin_context;
+
+    for event in shader_events.read() {
+        let (AssetEvent::Added(handle) | AssetEvent::Modified(handle)) = event;
+
+        let Some(shader) = shaders
+            .get(*handle)
+            .filter(|_| *handle == context.shader_handle)
+        else {
+            continue;
+        };
 
-            let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::Repeat,
-                address_mode_v: wgpu::AddressMode::Repeat,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
+        let rebuilt = validated(&device.0, || {
+            let module = device.0.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("sprite.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
             });
 
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
+            let pipelines: HashMap<_, _> = context
+                .pipelines
+                .keys()
+                .map(|&format| {
+                    let pipeline = create_pipeline(
+                        &device.0,
+                        &context.pipeline_layout,
+                        &module,
+                        format,
+                        context.sample_count,
+                    );
+                    (format, pipeline)
+                })
+                .collect();
+
+            (module, pipelines)
+        });
+
+        match rebuilt {
+            Ok((module, pipelines)) => {
+                log::info!("Rebuilt sprite pipelines");
+                context.shader = module;
+                context.pipelines = pipelines;
+            }
+            Err(error) => log::error!("Failed to rebuild sprite pipelines: {error}"),
+        }
+    }
+}
+
+/// Bind groups still point to the old texture after an image was reloaded.
+fn invalidate_sprite_textures_system(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut sprite_plugin_context: ResMut<SpritePluginContext>,
+) {
+    for event in image_events.read() {
+        if let AssetEvent::Modified(handle) = event {
+            sprite_plugin_context.texture_bind_groups.remove(handle);
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Sprite Render Pipeline"),
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vertex_main",
+            buffers: &[Vertex::desc(), Instance::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fragment_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // negative scales flip the winding order
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+#[derive(Resource)]
+pub struct SpritePluginContext {
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    shader_handle: Handle<Shader>,
+    /// one pipeline per render target format, created on first use
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+    /// MSAA samples of the pipelines, see [`RenderSettings::msaa_samples`]
+    sample_count: u32,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// one bind group per sampled image, created on first use
+    texture_bind_groups: HashMap<Handle<Image>, wgpu::BindGroup>,
+    vertex_buffer: wgpu::Buffer,
+    /// grows to fit the sprites of the largest frame so far
+    instance_buffer: Option<wgpu::Buffer>,
+}
+
+impl SpritePluginContext {
+    fn texture_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        images: &GpuImages,
+        handle: Handle<Image>,
+    ) -> Option<&wgpu::BindGroup> {
+        if !self.texture_bind_groups.contains_key(&handle) {
+            let texture_view = images.get(handle)?.create_view();
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.texture_bind_group_layout,
                 label: Some("Sprite Texture Bind Group"),
                 entries: &[
                     wgpu::BindGroupEntry {
@@ -156,91 +446,115 @@ impl Plugin for SpritePlugin {
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
                     },
                 ],
-            })
-        };
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sprite Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vertex_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fragment_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+            });
 
-        world.insert_resource(SpritePluginContext {
-            pipeline,
-            vertex_buffer,
-            bind_group,
-        });
+            self.texture_bind_groups.insert(handle, bind_group);
+        }
 
-        schedule.add_systems(draw_sprites_system.in_set(RenderStage::Render));
+        self.texture_bind_groups.get(&handle)
     }
 }
 
-#[derive(Resource)]
-pub struct SpritePluginContext {
-    pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
-}
-
+#[allow(clippy::too_many_arguments)]
 pub fn draw_sprites_system(
     device: Res<WgpuDevice>,
     cameras: Query<&Camera>,
+    sorted_cameras: Res<SortedCameras>,
+    sprites: Query<(&Sprite, &GlobalTransform)>,
+    images: Res<GpuImages>,
     queue: Res<WgpuQueue>,
     mut buffer_queue: EventWriter<CommandBufferFinishedEvent>,
-    sprite_plugin_context: Res<SpritePluginContext>,
+    mut sprite_plugin_context: ResMut<SpritePluginContext>,
 ) {
+    let context = &mut *sprite_plugin_context;
+
+    // back to front, there is no depth buffer. sprites on the same layer are
+    // grouped by texture so they can be drawn in a single batch
+    let mut sprites: Vec<_> = sprites
+        .iter()
+        .filter(|(sprite, _)| images.get(sprite.texture).is_some())
+        .collect();
+    sprites.sort_by(|(a_sprite, a), (b_sprite, b)| {
+        a.translation()
+            .z
+            .total_cmp(&b.translation().z)
+            .then(a_sprite.texture.cmp(&b_sprite.texture))
+    });
+
+    let mut instances = Vec::with_capacity(sprites.len());
+    let mut batches: Vec<(Handle<Image>, Range<u32>)> = Vec::new();
+
+    for (sprite, transform) in &sprites {
+        let size = images.get(sprite.texture).expect("filtered above").size;
+        let index = instances.len() as u32;
+        instances
+            .push(sprite.instance(transform, Vec2::new(size.width as f32, size.height as f32)));
+
+        match batches.last_mut() {
+            Some((texture, range)) if *texture == sprite.texture => range.end = index + 1,
+            _ => {
+                context.texture_bind_group(&device.0, &images, sprite.texture);
+                batches.push((sprite.texture, index..index + 1));
+            }
+        }
+    }
+
+    let instance_bytes: &[u8] = bytemuck::cast_slice(&instances);
+
+    if context
+        .instance_buffer
+        .as_ref()
+        .is_none_or(|buffer| buffer.size() < instance_bytes.len() as u64)
+    {
+        context.instance_buffer = Some(device.0.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Instance Buffer"),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            size: (instance_bytes.len() as u64).max(std::mem::size_of::<Instance>() as u64),
+            mapped_at_creation: false,
+        }));
+    }
+
+    let instance_buffer = context.instance_buffer.as_ref().expect("created above");
+    queue.0.write_buffer(instance_buffer, 0, instance_bytes);
+
     let mut encoder = device
         .0
         .create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Sprites"),
         });
 
-    queue.0.write_buffer(
-        &sprite_plugin_context.vertex_buffer,
-        0,
-        bytemuck::cast_slice(VERTICES),
-    );
+    for &entity in &sorted_cameras.0 {
+        let Ok(camera) = cameras.get(entity) else {
+            continue;
+        };
+
+        let format = camera.format.expect("prepared cameras have a format");
+        let pipeline = context.pipelines.entry(format).or_insert_with(|| {
+            create_pipeline(
+                &device.0,
+                &context.pipeline_layout,
+                &context.shader,
+                format,
+                context.sample_count,
+            )
+        });
+
+        let target = camera.view.as_ref().unwrap();
+        let (view, resolve_target) = match &camera.multisampled_view {
+            Some(multisampled) => (multisampled, Some(target)),
+            None => (target, None),
+        };
 
-    for camera in cameras.iter() {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: camera.view.as_ref().unwrap(),
-                resolve_target: None,
+                view,
+                resolve_target,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    load: camera.load_op,
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -249,11 +563,31 @@ pub fn draw_sprites_system(
             occlusion_query_set: None,
         });
 
-        render_pass.set_pipeline(&sprite_plugin_context.pipeline);
-        render_pass.set_vertex_buffer(0, sprite_plugin_context.vertex_buffer.slice(..));
-        render_pass.set_bind_group(0, &sprite_plugin_context.bind_group, &[]);
+        if let Some(viewport) = camera.physical_viewport {
+            render_pass.set_viewport(
+                viewport.position.x as f32,
+                viewport.position.y as f32,
+                viewport.size.x as f32,
+                viewport.size.y as f32,
+                0.0,
+                1.0,
+            );
+        }
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(0, context.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
         render_pass.set_bind_group(1, camera.bind_group.as_ref().unwrap(), &[]);
-        render_pass.draw(0..4, 0..1);
+
+        for (texture, instances) in &batches {
+            // a texture cant be sampled while it is being rendered into
+            if camera.target == RenderTarget::Image(*texture) {
+                continue;
+            }
+
+            render_pass.set_bind_group(0, &context.texture_bind_groups[texture], &[]);
+            render_pass.draw(0..VERTICES.len() as u32, instances.clone());
+        }
     }
 
     buffer_queue.send(CommandBufferFinishedEvent(encoder.finish()));
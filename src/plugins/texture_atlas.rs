@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use bevy_ecs::{
+    component::Component,
+    system::{Query, Res},
+};
+use glam::UVec2;
+
+use super::{
+    assets::{Asset, Assets, Handle, LoadContext},
+    images::Image,
+    sprites::{Rect, Sprite},
+};
+
+/// Largest texture the atlas builder packs into, supported by every wgpu backend.
+const MAX_ATLAS_SIZE: u32 = 8192;
+
+/// Many sprites sharing one texture, so they can be drawn in a single batch.
+///
+/// Loaded from `.atlas` files, a line based format that either packs the
+/// listed images at load time:
+///
+/// ```text
+/// # pixels between packed images
+/// padding 2
+/// image coin.png
+/// image heart.png
+/// ```
+///
+/// or splits a pre-built sprite sheet into a grid of tiles, row by row:
+///
+/// ```text
+/// sheet characters.png
+/// tile 16 16
+/// grid 8 4
+/// # optional, gap between tiles and distance of the first tile from the top left
+/// padding 1 1
+/// offset 0 0
+/// ```
+///
+/// Paths are relative to the `.atlas` file. Only the `.atlas` file itself is
+/// watched for changes.
+pub struct TextureAtlas {
+    pub image: Handle<Image>,
+    /// Region of each sprite in the image, in pixels
+    pub rects: Vec<Rect>,
+    /// index of each packed image by the name it was added with
+    names: HashMap<String, usize>,
+}
+
+impl TextureAtlas {
+    /// Splits a sprite sheet into `columns` by `rows` tiles, numbered row by row
+    /// from the top left.
+    pub fn from_grid(
+        image: Handle<Image>,
+        tile_size: UVec2,
+        columns: u32,
+        rows: u32,
+        padding: UVec2,
+        offset: UVec2,
+    ) -> Self {
+        let rects = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| UVec2::new(column, row)))
+            .map(|cell| {
+                let min = offset + cell * (tile_size + padding);
+
+                Rect {
+                    min: min.as_vec2(),
+                    max: (min + tile_size).as_vec2(),
+                }
+            })
+            .collect();
+
+        Self {
+            image,
+            rects,
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rect(&self, index: usize) -> Option<Rect> {
+        self.rects.get(index).copied()
+    }
+
+    /// Index of an image packed by [`TextureAtlasBuilder`], by the name it was
+    /// added with. For `.atlas` files that is the path as written in the file.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+}
+
+impl Asset for TextureAtlas {
+    fn load(bytes: &[u8], context: &mut LoadContext) -> Result<Self, anyhow::Error> {
+        let source = std::str::from_utf8(bytes)?;
+
+        let mut builder = TextureAtlasBuilder::new();
+        let mut sheet = None;
+        let mut tile_size = None;
+        let mut grid = None;
+        let mut padding = None;
+        let mut offset = UVec2::ZERO;
+
+        let path = context.path().to_owned();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let error =
+                |message: &str| anyhow::anyhow!("{}:{}: {message}", path.display(), number + 1);
+
+            match key {
+                "image" => {
+                    let image = Image::load(&context.read(value)?, context)?;
+                    builder = builder.add(value, image.0);
+                }
+                "sheet" => sheet = Some(value.to_owned()),
+                "tile" => {
+                    tile_size = Some(parse_pair(value).ok_or(error("expected width height"))?)
+                }
+                "grid" => grid = Some(parse_pair(value).ok_or(error("expected columns rows"))?),
+                "padding" => {
+                    padding = Some(match parse_pair(value) {
+                        Some(padding) => padding,
+                        None => {
+                            let padding = value.parse().map_err(|_| error("expected padding"))?;
+                            UVec2::splat(padding)
+                        }
+                    })
+                }
+                "offset" => offset = parse_pair(value).ok_or(error("expected x y"))?,
+                _ => return Err(error(&format!("unknown key {key}"))),
+            }
+        }
+
+        let Some(sheet) = sheet else {
+            if let Some(padding) = padding {
+                builder = builder.padding(padding.x);
+            }
+
+            return builder.build(|image| context.add_labeled_asset("image", image));
+        };
+
+        let tile_size = tile_size.ok_or(anyhow::anyhow!("Sprite sheet without tile size"))?;
+        let grid = grid.ok_or(anyhow::anyhow!("Sprite sheet without grid"))?;
+
+        let image = Image::load(&context.read(&sheet)?, context)?;
+        let image = context.add_labeled_asset("image", image);
+
+        Ok(Self::from_grid(
+            image,
+            tile_size,
+            grid.x,
+            grid.y,
+            padding.unwrap_or(UVec2::ZERO),
+            offset,
+        ))
+    }
+}
+
+fn parse_pair(value: &str) -> Option<UVec2> {
+    let mut parts = value.split_whitespace().map(str::parse);
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Some(UVec2::new(x, y)),
+        _ => None,
+    }
+}
+
+/// Packs images into a single texture, tallest first onto shelves.
+///
+/// Every image is surrounded by `padding` pixels repeating its outermost
+/// pixels, so filtering near the edge of a sprite does not pick up its
+/// neighbours.
+pub struct TextureAtlasBuilder {
+    padding: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl Default for TextureAtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextureAtlasBuilder {
+    pub fn new() -> Self {
+        Self {
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn add(mut self, name: impl Into<String>, image: image::RgbaImage) -> Self {
+        self.images.push((name.into(), image));
+        self
+    }
+
+    /// Packs the added images. `add_image` stores the packed image, e.g. in
+    /// [`Assets<Image>`], and returns its handle.
+    pub fn build(
+        self,
+        add_image: impl FnOnce(Image) -> Handle<Image>,
+    ) -> Result<TextureAtlas, anyhow::Error> {
+        let padded = |image: &image::RgbaImage| {
+            UVec2::from(image.dimensions()) + UVec2::splat(self.padding * 2)
+        };
+
+        let mut order: Vec<_> = (0..self.images.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(self.images[index].1.height()));
+
+        let area: u32 = self
+            .images
+            .iter()
+            .map(|(_, image)| padded(image).x * padded(image).y)
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|(_, image)| padded(image).x)
+            .max()
+            .unwrap_or(1);
+
+        // grow a power of two width until the shelves fit into a square
+        let mut width = widest.max((area as f32).sqrt() as u32).next_power_of_two();
+        let (positions, height) = loop {
+            let (positions, height) = self.pack_shelves(&order, width);
+
+            if height <= width || width >= MAX_ATLAS_SIZE {
+                break (positions, height);
+            }
+
+            width *= 2;
+        };
+
+        if width > MAX_ATLAS_SIZE || height > MAX_ATLAS_SIZE {
+            anyhow::bail!(
+                "Texture atlas of {width}x{height} exceeds {MAX_ATLAS_SIZE}x{MAX_ATLAS_SIZE}"
+            );
+        }
+
+        let mut atlas = image::RgbaImage::new(width, height.max(1));
+        let mut rects = Vec::with_capacity(self.images.len());
+        let mut names = HashMap::with_capacity(self.images.len());
+
+        for (index, ((name, image), position)) in self.images.into_iter().zip(positions).enumerate()
+        {
+            let min = position + UVec2::splat(self.padding);
+            let size = UVec2::from(image.dimensions());
+
+            // copy the image including its padding, clamping to extrude the edges
+            let padding = self.padding as i64;
+            for y in -padding..size.y as i64 + padding {
+                for x in -padding..size.x as i64 + padding {
+                    let source = image.get_pixel(
+                        x.clamp(0, size.x as i64 - 1) as u32,
+                        y.clamp(0, size.y as i64 - 1) as u32,
+                    );
+
+                    atlas.put_pixel(
+                        (min.x as i64 + x) as u32,
+                        (min.y as i64 + y) as u32,
+                        *source,
+                    );
+                }
+            }
+
+            rects.push(Rect {
+                min: min.as_vec2(),
+                max: (min + size).as_vec2(),
+            });
+            names.insert(name, index);
+        }
+
+        Ok(TextureAtlas {
+            image: add_image(Image(atlas)),
+            rects,
+            names,
+        })
+    }
+
+    /// Top left corner of every padded image, by index, and the height used.
+    fn pack_shelves(&self, order: &[usize], width: u32) -> (Vec<UVec2>, u32) {
+        let mut positions = vec![UVec2::ZERO; self.images.len()];
+        let mut cursor = UVec2::ZERO;
+        let mut shelf_height = 0;
+
+        for &index in order {
+            let (image_width, image_height) = self.images[index].1.dimensions();
+            let size = UVec2::new(image_width, image_height) + UVec2::splat(self.padding * 2);
+
+            if cursor.x + size.x > width {
+                cursor = UVec2::new(0, cursor.y + shelf_height);
+                shelf_height = 0;
+            }
+
+            positions[index] = cursor;
+            cursor.x += size.x;
+            shelf_height = shelf_height.max(size.y);
+        }
+
+        (positions, cursor.y + shelf_height)
+    }
+}
+
+/// Shows one region of a [`TextureAtlas`] on the entity's [`Sprite`], whose
+/// texture and source rect are overwritten before every frame.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TextureAtlasSprite {
+    pub atlas: Handle<TextureAtlas>,
+    pub index: usize,
+}
+
+impl TextureAtlasSprite {
+    pub fn new(atlas: Handle<TextureAtlas>, index: usize) -> Self {
+        Self { atlas, index }
+    }
+}
+
+pub fn update_atlas_sprites_system(
+    atlases: Res<Assets<TextureAtlas>>,
+    mut sprites: Query<(&mut Sprite, &TextureAtlasSprite)>,
+) {
+    for (mut sprite, atlas_sprite) in &mut sprites {
+        let Some(atlas) = atlases.get(atlas_sprite.atlas) else {
+            continue;
+        };
+
+        // out of range indices keep showing the previous region
+        let Some(rect) = atlas.rect(atlas_sprite.index) else {
+            continue;
+        };
+
+        if sprite.texture != atlas.image || sprite.source_rect != Some(rect) {
+            sprite.texture = atlas.image;
+            sprite.source_rect = Some(rect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::plugins::assets::LoadContext;
+
+    fn rect(min: (f32, f32), max: (f32, f32)) -> Rect {
+        Rect {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    /// Packs `builder`, keeping the packed image.
+    fn build(builder: TextureAtlasBuilder) -> Result<(TextureAtlas, RgbaImage), anyhow::Error> {
+        let mut packed = None;
+        let atlas = builder.build(|image| {
+            packed = Some(image.0);
+            Handle::new()
+        })?;
+
+        Ok((atlas, packed.unwrap()))
+    }
+
+    #[test]
+    fn packed_rects_do_not_overlap_and_keep_their_padding() {
+        let sizes = [(10, 4), (3, 7), (5, 5), (8, 2), (1, 1), (6, 9), (12, 3)];
+        let builder = sizes.iter().enumerate().fold(
+            TextureAtlasBuilder::new().padding(2),
+            |builder, (index, &(w, h))| builder.add(index.to_string(), RgbaImage::new(w, h)),
+        );
+
+        let (atlas, image) = build(builder).unwrap();
+        let bounds = glam::Vec2::new(image.width() as f32, image.height() as f32);
+
+        for (index, a) in atlas.rects.iter().enumerate() {
+            assert_eq!(atlas.index_of(&index.to_string()), Some(index));
+            assert_eq!(a.max - a.min, UVec2::from(sizes[index]).as_vec2());
+            assert!(a.min.cmpge(glam::Vec2::splat(2.0)).all());
+            assert!((a.max + 2.0).cmple(bounds).all());
+
+            for b in &atlas.rects[index + 1..] {
+                // at least the padding of both images between them
+                let apart = a.max.x + 4.0 <= b.min.x
+                    || b.max.x + 4.0 <= a.min.x
+                    || a.max.y + 4.0 <= b.min.y
+                    || b.max.y + 4.0 <= a.min.y;
+                assert!(apart, "{a:?} and {b:?} are too close");
+            }
+        }
+    }
+
+    #[test]
+    fn padding_repeats_the_edge_pixels() {
+        let mut source = RgbaImage::new(3, 2);
+        for (x, y, pixel) in source.enumerate_pixels_mut() {
+            *pixel = Rgba([x as u8 * 80, y as u8 * 120, 7, 255]);
+        }
+
+        let (atlas, image) = build(
+            TextureAtlasBuilder::new()
+                .padding(2)
+                .add("source", source.clone()),
+        )
+        .unwrap();
+        let min = atlas.rects[0].min.as_uvec2().as_ivec2();
+
+        for y in -2..4 {
+            for x in -2..5 {
+                let expected = source.get_pixel(x.clamp(0, 2) as u32, y.clamp(0, 1) as u32);
+                let actual = image.get_pixel((min.x + x) as u32, (min.y + y) as u32);
+                assert_eq!(actual, expected, "pixel {x} {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        for (width, height) in [(MAX_ATLAS_SIZE, 1), (1, MAX_ATLAS_SIZE)] {
+            let builder = TextureAtlasBuilder::new().add("huge", RgbaImage::new(width, height));
+            assert!(build(builder).is_err(), "{width}x{height} was packed");
+        }
+
+        let builder = TextureAtlasBuilder::new()
+            .padding(0)
+            .add("exact", RgbaImage::new(MAX_ATLAS_SIZE, 1));
+        assert!(build(builder).is_ok());
+    }
+
+    #[test]
+    fn grid_sheets_are_split_with_padding_and_offset() {
+        let directory = std::env::temp_dir().join("mush-atlas-grid");
+        std::fs::create_dir_all(&directory).unwrap();
+        RgbaImage::new(48, 24)
+            .save(directory.join("sheet.png"))
+            .unwrap();
+
+        let source = "sheet sheet.png\ntile 8 8\ngrid 4 2\npadding 2 1\noffset 3 4\n";
+        let path = directory.join("sheet.atlas");
+        let mut labels = HashMap::new();
+        let mut context = LoadContext::new(&path, 0, &mut labels);
+
+        let atlas = TextureAtlas::load(source.as_bytes(), &mut context).unwrap();
+
+        assert_eq!(atlas.len(), 8);
+        assert_eq!(atlas.rect(0), Some(rect((3.0, 4.0), (11.0, 12.0))));
+        assert_eq!(atlas.rect(1), Some(rect((13.0, 4.0), (21.0, 12.0))));
+        assert_eq!(atlas.rect(3), Some(rect((33.0, 4.0), (41.0, 12.0))));
+        assert_eq!(atlas.rect(4), Some(rect((3.0, 13.0), (11.0, 21.0))));
+        assert_eq!(atlas.rect(7), Some(rect((33.0, 13.0), (41.0, 21.0))));
+        assert_eq!(atlas.rect(8), None);
+    }
+}
@@ -0,0 +1,304 @@
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::{With, Without},
+    schedule::IntoSystemConfigs as _,
+    world::World,
+};
+use glam::{Mat4, Quat, Vec3};
+
+use crate::{application::RenderSchedule, resources::InterpolationAlpha};
+
+use super::{rendering::RenderStage, App, Plugin};
+
+/// Placement relative to the [`Parent`], or to the world for entities without one.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_xyz(x: f32, y: f32, z: f32) -> Self {
+        Self::from_translation(Vec3::new(x, y, z))
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn compute_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Blends towards `other`, `t` = 0 is `self` and `t` = 1 is `other`.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Placement in the world, computed from the [`Transform`]s of the entity and
+/// its ancestors before every frame. Added automatically to entities with a
+/// [`Transform`].
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct GlobalTransform(pub Mat4);
+
+impl GlobalTransform {
+    pub const IDENTITY: Self = Self(Mat4::IDENTITY);
+
+    pub fn translation(&self) -> Vec3 {
+        self.0.w_axis.truncate()
+    }
+
+    /// The local -z axis in world space, where cameras look.
+    pub fn forward(&self) -> Vec3 {
+        self.0.transform_vector3(Vec3::NEG_Z).normalize_or_zero()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.0.transform_vector3(Vec3::Y).normalize_or_zero()
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Entity this one is attached to, use [`set_parent`] to keep [`Children`] in sync.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Parent(pub Entity);
+
+#[derive(Component, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Children(pub Vec<Entity>);
+
+/// Attaches `child` to `parent`, detaching it from its previous parent. Returns
+/// false and changes nothing if `parent` is `child` or one of its descendants.
+pub fn set_parent(world: &mut World, child: Entity, parent: Entity) -> bool {
+    let mut ancestor = Some(parent);
+    while let Some(entity) = ancestor {
+        if entity == child {
+            return false;
+        }
+
+        ancestor = world.get::<Parent>(entity).map(|parent| parent.0);
+    }
+
+    remove_parent(world, child);
+
+    world.entity_mut(child).insert(Parent(parent));
+    match world.get_mut::<Children>(parent) {
+        Some(mut children) => children.0.push(child),
+        None => {
+            world.entity_mut(parent).insert(Children(vec![child]));
+        }
+    }
+
+    true
+}
+
+/// Detaches `child` from its parent, it keeps its [`Transform`] which is now
+/// relative to the world.
+pub fn remove_parent(world: &mut World, child: Entity) {
+    let Some(Parent(parent)) = world.entity_mut(child).take::<Parent>() else {
+        return;
+    };
+
+    if let Some(mut children) = world.get_mut::<Children>(parent) {
+        children.0.retain(|&entity| entity != child);
+    }
+}
+
+/// Despawns `entity` together with all its descendants.
+pub fn despawn_recursive(world: &mut World, entity: Entity) {
+    remove_parent(world, entity);
+
+    let mut stack = vec![entity];
+    while let Some(entity) = stack.pop() {
+        if let Some(children) = world.get::<Children>(entity) {
+            stack.extend_from_slice(&children.0);
+        }
+
+        world.despawn(entity);
+    }
+}
+
+/// Renders the entity between its [`Transform`] of the previous and the current
+/// update tick, blended by the [`InterpolationAlpha`]. Smooths movement when
+/// frames are rendered more often than the simulation ticks, at the cost of
+/// showing it up to one tick late.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct TransformInterpolation {
+    previous: Option<Transform>,
+}
+
+impl TransformInterpolation {
+    /// Shows the current transform right away, e.g. after teleporting.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Remembers the transforms [`TransformInterpolation`] blends from, called
+/// before every update tick.
+pub fn store_previous_transforms(world: &mut World) {
+    for (transform, mut interpolation) in world
+        .query::<(&Transform, &mut TransformInterpolation)>()
+        .iter_mut(world)
+    {
+        interpolation.previous = Some(*transform);
+    }
+}
+
+/// Computes the [`GlobalTransform`] of every entity, parents first.
+///
+/// Children of despawned parents become roots and keep their last world
+/// placement.
+pub fn propagate_transforms_system(world: &mut World) {
+    let missing: Vec<_> = world
+        .query_filtered::<Entity, (With<Transform>, Without<GlobalTransform>)>()
+        .iter(world)
+        .collect();
+
+    for entity in missing {
+        world.entity_mut(entity).insert(GlobalTransform::IDENTITY);
+    }
+
+    let orphans: Vec<_> = world
+        .query::<(Entity, &Parent)>()
+        .iter(world)
+        .filter(|(_, parent)| world.get_entity(parent.0).is_none())
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for orphan in orphans {
+        let mut entity = world.entity_mut(orphan);
+        entity.remove::<Parent>();
+
+        if let Some(global) = entity.get::<GlobalTransform>().copied() {
+            entity.insert(Transform::from_matrix(global.0));
+        }
+    }
+
+    let stale: Vec<_> = world
+        .query::<(Entity, &Children)>()
+        .iter(world)
+        .filter(|(_, children)| {
+            children
+                .0
+                .iter()
+                .any(|&child| world.get_entity(child).is_none())
+        })
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for entity in stale {
+        let alive: Vec<_> = world.get::<Children>(entity).unwrap().0.clone();
+        let alive = alive
+            .into_iter()
+            .filter(|&child| world.get_entity(child).is_some())
+            .collect();
+
+        world.entity_mut(entity).insert(Children(alive));
+    }
+
+    let roots: Vec<_> = world
+        .query_filtered::<Entity, (With<Transform>, Without<Parent>)>()
+        .iter(world)
+        .collect();
+
+    let alpha = world
+        .get_resource::<InterpolationAlpha>()
+        .map_or(1.0, |alpha| alpha.0);
+
+    for root in roots {
+        propagate(world, root, Mat4::IDENTITY, alpha);
+    }
+}
+
+fn propagate(world: &mut World, entity: Entity, parent: Mat4, alpha: f32) {
+    let Some(transform) = world.get::<Transform>(entity) else {
+        return;
+    };
+
+    let previous = world
+        .get::<TransformInterpolation>(entity)
+        .and_then(|interpolation| interpolation.previous);
+
+    let local = match previous {
+        Some(previous) => previous.lerp(transform, alpha),
+        None => *transform,
+    };
+
+    let global = parent * local.compute_matrix();
+
+    if let Some(mut global_transform) = world.get_mut::<GlobalTransform>(entity) {
+        // only write on change, to keep change detection meaningful
+        if global_transform.0 != global {
+            global_transform.0 = global;
+        }
+    }
+
+    let Some(children) = world.get::<Children>(entity) else {
+        return;
+    };
+
+    for child in children.0.clone() {
+        propagate(world, child, global, alpha);
+    }
+}
+
+/// Propagates transforms after the update ticks, before anything is rendered.
+pub struct TransformPlugin;
+
+impl Plugin for TransformPlugin {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        app.add_systems(
+            RenderSchedule,
+            propagate_transforms_system.before(RenderStage::Prepare),
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use std::{fmt, path::Path, path::PathBuf};
+
+use crate::plugins::input::InputEvent;
+
+/// Input events together with the update tick they arrived before, and the
+/// frames they arrived between, counted from the start of the recording.
+///
+/// Stored as text, one `<tick> <event>` line per event and one
+/// `frame <time scale> <running|paused> <alpha> <frame delta|-> <tick deltas>`
+/// line per frame, in the order they happened:
+///
+/// ```text
+/// 0 key KeyD pressed
+/// frame 1 running 0.25 - 0.016666668 0.016666668
+/// 2 key KeyD released
+/// 2 cursor 320 240
+/// frame 0.5 paused 0.5 -
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct InputRecording {
+    /// Sorted by tick
+    pub events: Vec<(u64, InputEvent)>,
+    /// Each with the number of events that arrived before it, the first ones
+    /// of `events`
+    pub frames: Vec<(usize, RecordedFrame)>,
+    /// Update ticks that ran while recording
+    pub ticks: u64,
+}
+
+/// A frame of the recorded application, with everything it took from the
+/// clock. Time scale and pause are applied once per frame, before its ticks,
+/// so replaying has to apply them at the same points.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RecordedFrame {
+    /// [`Time`](crate::resources::Time)'s scale when the frame started
+    pub time_scale: f32,
+    pub paused: bool,
+    /// Deltas of the update ticks the frame ran, already scaled
+    pub deltas: Vec<f32>,
+    /// Delta of the per frame update, only
+    /// [`TimestepMode::Hybrid`](crate::timestep_scheduler::TimestepMode::Hybrid) has one
+    pub frame_delta: Option<f32>,
+    /// See [`InterpolationAlpha`](crate::resources::InterpolationAlpha)
+    pub alpha: f32,
+}
+
+impl InputRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, tick: u64, event: InputEvent) {
+        debug_assert!(self.events.last().is_none_or(|(last, _)| *last <= tick));
+
+        self.events.push((tick, event));
+        self.ticks = self.ticks.max(tick);
+    }
+
+    /// Ends a frame that ran after the events pushed so far.
+    pub fn push_frame(&mut self, frame: RecordedFrame) {
+        self.ticks += frame.deltas.len() as u64;
+        self.frames.push((self.events.len(), frame));
+    }
+
+    /// Events that arrived before `tick` ran.
+    pub fn events_before(&self, tick: u64) -> impl Iterator<Item = &InputEvent> {
+        let start = self.events.partition_point(|(t, _)| *t < tick);
+        let end = self.events.partition_point(|(t, _)| *t <= tick);
+
+        self.events[start..end].iter().map(|(_, event)| event)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+
+        source
+            .parse()
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for InputRecording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut written = 0;
+
+        for (events, frame) in &self.frames {
+            for (tick, event) in &self.events[written..*events] {
+                writeln!(f, "{tick} {event}")?;
+            }
+            written = *events;
+
+            writeln!(f, "frame {frame}")?;
+        }
+
+        // input after the last frame
+        for (tick, event) in &self.events[written..] {
+            writeln!(f, "{tick} {event}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for InputRecording {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut recording = Self::default();
+
+        for (number, line) in source.lines().enumerate() {
+            // not trimmed at the end, typed text can end in spaces
+            let line = line.trim_start();
+            if line.trim_end().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |error| anyhow::anyhow!("line {}: {error}", number + 1);
+
+            let (tick, event) = line
+                .split_once(' ')
+                .ok_or_else(|| error(anyhow::anyhow!("expected tick and event")))?;
+
+            if tick == "frame" {
+                recording.push_frame(event.parse().map_err(error)?);
+                continue;
+            }
+
+            let tick: u64 = tick.parse().map_err(|e| error(anyhow::Error::new(e)))?;
+            if recording
+                .events
+                .last()
+                .is_some_and(|(last, _)| *last > tick)
+            {
+                return Err(error(anyhow::anyhow!("tick {tick} is out of order")));
+            }
+
+            recording.push(tick, event.parse().map_err(error)?);
+        }
+
+        Ok(recording)
+    }
+}
+
+impl fmt::Display for RecordedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.paused {
+            true => "paused",
+            false => "running",
+        };
+        write!(f, "{} {state} {}", self.time_scale, self.alpha)?;
+
+        match self.frame_delta {
+            Some(delta) => write!(f, " {delta}")?,
+            None => write!(f, " -")?,
+        }
+
+        for delta in &self.deltas {
+            write!(f, " {delta}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for RecordedFrame {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let words: Vec<_> = source.split_whitespace().collect();
+
+        let [time_scale, state, alpha, frame_delta, deltas @ ..] = words.as_slice() else {
+            anyhow::bail!("expected time scale, running or paused, alpha and frame delta");
+        };
+
+        let paused = match *state {
+            "running" => false,
+            "paused" => true,
+            _ => anyhow::bail!("expected running or paused, got {state}"),
+        };
+
+        let frame_delta = match *frame_delta {
+            "-" => None,
+            delta => Some(delta.parse()?),
+        };
+
+        Ok(Self {
+            time_scale: time_scale.parse()?,
+            paused,
+            deltas: deltas
+                .iter()
+                .map(|delta| delta.parse())
+                .collect::<Result<_, _>>()?,
+            frame_delta,
+            alpha: alpha.parse()?,
+        })
+    }
+}
+
+/// Collects the input an application receives and writes it to `path` when
+/// dropped, i.e. when the application exits.
+pub(crate) struct InputRecorder {
+    path: PathBuf,
+    recording: InputRecording,
+}
+
+impl InputRecorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            recording: InputRecording::new(),
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, event: &InputEvent) {
+        self.recording.push(tick, event.clone());
+    }
+
+    pub fn finish_frame(&mut self, frame: RecordedFrame) {
+        self.recording.push_frame(frame);
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        match self.recording.save(&self.path) {
+            Ok(()) => log::info!("Saved input recording to {}", self.path.display()),
+            Err(error) => log::error!(
+                "Failed to save input recording to {}: {error}",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(time_scale: f32, paused: bool, deltas: Vec<f32>) -> RecordedFrame {
+        RecordedFrame {
+            time_scale,
+            paused,
+            deltas,
+            frame_delta: None,
+            alpha: 0.5,
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_between_their_events() {
+        let mut recording = InputRecording::new();
+        recording.push(0, InputEvent::FocusLost);
+        recording.push_frame(frame(1.0, false, vec![0.1, 0.1]));
+        recording.push_frame(RecordedFrame {
+            frame_delta: Some(0.016666668),
+            ..frame(1.0, false, vec![])
+        });
+        recording.push(2, InputEvent::CursorLeft);
+        recording.push_frame(frame(0.5, true, vec![0.0]));
+        recording.push(3, InputEvent::FocusLost);
+
+        let text = recording.to_string();
+        assert_eq!(
+            text,
+            "0 focus-lost\n\
+             frame 1 running 0.5 - 0.1 0.1\n\
+             frame 1 running 0.5 0.016666668\n\
+             2 cursor-left\n\
+             frame 0.5 paused 0.5 - 0\n\
+             3 focus-lost\n"
+        );
+
+        let parsed: InputRecording = text.parse().unwrap();
+        assert_eq!(parsed, recording);
+        assert_eq!(parsed.ticks, 3);
+    }
+}
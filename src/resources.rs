@@ -1,5 +1,84 @@
 use bevy_ecs::system::Resource;
 
-#[derive(Resource)]
-pub struct Delta(pub f32);
+/// Simulated time, advanced by every update tick.
+///
+/// The scale speeds up or slows down the simulation without affecting the
+/// frame rate. With a fixed timestep, the delta stays the same and ticks run
+/// more or less often. While paused, ticks keep running so input is handled,
+/// with a delta of 0.
+#[derive(Resource, Clone, Debug)]
+pub struct Time {
+    delta: f32,
+    elapsed: f64,
+    ticks: u64,
+    scale: f32,
+    paused: bool,
+}
 
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            delta: 0.0,
+            elapsed: 0.0,
+            ticks: 0,
+            scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl Time {
+    /// Seconds simulated by the current tick. In the per frame schedule of
+    /// [`TimestepMode::Hybrid`](crate::timestep_scheduler::TimestepMode::Hybrid),
+    /// the scaled seconds since the previous frame.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Seconds simulated by all ticks so far.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Update ticks run so far, including the current one.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Takes effect with the next frame. Negative scales are clamped to 0.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub(crate) fn advance_tick(&mut self, delta: f32) {
+        self.delta = delta;
+        self.elapsed += delta as f64;
+        self.ticks += 1;
+    }
+
+    pub(crate) fn set_frame_delta(&mut self, delta: f32) {
+        self.delta = delta;
+    }
+}
+
+/// How far rendering is between the previous and the current update tick, from
+/// 0 to 1. Updated before every frame is rendered, render systems can use it to
+/// interpolate between the previous and current state of the simulation.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct InterpolationAlpha(pub f32);
@@ -1,57 +1,607 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+use crate::clock::Clock;
 
 pub trait TimestepScheduler {
+    /// Runs the update ticks that are due, `function` gets the tick's delta.
     fn update(&mut self, function: impl FnMut(f32));
-    fn render(&mut self, function: impl FnMut());
+    /// Runs once per frame after the ticks, with the time since the previous
+    /// frame. Only schedulers with a per frame schedule call `function`.
+    fn frame_update(&mut self, _function: impl FnMut(f32)) {}
+    /// `function` gets the interpolation alpha, see [`InterpolationAlpha`](crate::resources::InterpolationAlpha).
+    fn render(&mut self, function: impl FnMut(f32));
+    /// Waits for the rest of the target frame time, if the frame rate is limited.
     fn after_frame(&mut self);
+    /// Turned off while presenting waits for vsync, which limits the frame rate
+    /// already. On by default.
+    fn limit_frame_rate(&mut self, limit: bool);
+    /// Speeds up or slows down the simulation, see [`Time`](crate::resources::Time).
+    fn set_time_scale(&mut self, scale: f32, paused: bool);
+    /// Update ticks run so far.
+    fn ticks(&self) -> u64;
+    /// Counts a tick that ran without the clock, e.g. a replayed one.
+    fn count_tick(&mut self);
+}
+
+/// How the application advances its simulation, see [`ApplicationBuilder::timestep`](crate::application::ApplicationBuilder::timestep).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TimestepMode {
+    /// One update per frame with the time the frame took.
+    Variable,
+    /// Updates at the tick rate, frames in between are interpolated.
+    #[default]
+    Fixed,
+    /// Like [`TimestepMode::Fixed`], plus a schedule that runs once per frame
+    /// with the frame's delta, for things that should not wait for a tick.
+    Hybrid,
 }
 
+/// Frames that took longer are slowed down, so the simulation does not jump
+/// e.g. after the window was dragged.
+pub const MAX_FRAME_DELTA: f32 = 0.25;
+
+/// Applies [`Time`](crate::resources::Time)'s scale and pause to clock time.
+#[derive(Clone, Copy, Debug)]
+struct TimeScale {
+    scale: f32,
+    paused: bool,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl TimeScale {
+    /// Simulated time passing in `real` time. Paused time still passes, ticks
+    /// keep running with a delta of 0 so input is still handled.
+    fn simulated(&self, real: Duration) -> Duration {
+        match self.paused {
+            true => real,
+            false => real.mul_f64(self.scale as f64),
+        }
+    }
+
+    fn delta(&self, delta: f32) -> f32 {
+        match self.paused {
+            true => 0.0,
+            false => delta,
+        }
+    }
+}
+
+/// Ticks the default maximum per frame, see [`FixedUpdateScheduler::with_max_ticks_per_frame`].
+pub const DEFAULT_MAX_TICKS_PER_FRAME: u32 = 5;
+
 pub struct FixedUpdateScheduler {
-    target_tps: u16,
+    clock: Box<dyn Clock>,
+    time_scale: TimeScale,
     delta: f32,
-    last_tick: Instant,
+    tick_duration: Duration,
+    /// Time not yet consumed by ticks, always below `tick_duration` after an update
+    accumulator: Duration,
+    last_update: Duration,
     target_frame_time: Duration,
+    limit_frame_rate: bool,
+    max_ticks_per_frame: u32,
+    ticks: u64,
 }
 
 impl FixedUpdateScheduler {
-    pub fn new(target_tps: u16, target_fps: u16) -> Self {
-        assert!(
-            target_tps >= target_fps,
-            "TODO: this case isnt handled correctly yet"
-        );
+    /// Frames are paced to `target_fps`, which can be higher than `target_tps`.
+    /// Frames between ticks then render the same state, unless it is
+    /// interpolated, see [`InterpolationAlpha`](crate::resources::InterpolationAlpha).
+    /// Both rates have to be positive.
+    pub fn new(target_tps: u16, target_fps: u16, clock: Box<dyn Clock>) -> Self {
+        assert!(target_tps > 0, "tick rate has to be positive");
+        assert!(target_fps > 0, "frame rate has to be positive");
 
         Self {
-            target_tps,
+            last_update: clock.now(),
+            clock,
+            time_scale: TimeScale::default(),
             delta: 1.0 / target_tps as f32,
-            last_tick: Instant::now(),
-            target_frame_time: Duration::from_secs_f32(1.0 / target_fps as f32),
+            tick_duration: Duration::from_secs_f64(1.0 / target_tps as f64),
+            accumulator: Duration::ZERO,
+            target_frame_time: Duration::from_secs_f64(1.0 / target_fps as f64),
+            limit_frame_rate: true,
+            max_ticks_per_frame: DEFAULT_MAX_TICKS_PER_FRAME,
+            ticks: 0,
         }
     }
+
+    /// Ticks that run at most in a single frame. If ticking takes longer than
+    /// the time it simulates, the frames would run more and more ticks to catch
+    /// up. Instead, time beyond this many ticks is dropped and the simulation
+    /// slows down.
+    pub fn with_max_ticks_per_frame(mut self, max_ticks_per_frame: u32) -> Self {
+        self.max_ticks_per_frame = max_ticks_per_frame.max(1);
+        self
+    }
+
+    /// How far the time is between the last tick and the next one, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
 }
 
 impl TimestepScheduler for FixedUpdateScheduler {
     #[inline]
     fn update(&mut self, mut function: impl FnMut(f32)) {
-        let amount_ticks =
-            (self.last_tick.elapsed().as_secs_f32() * self.target_tps as f32) as usize;
+        let now = self.clock.now();
+        self.accumulator += self.time_scale.simulated(now - self.last_update);
+        self.last_update = now;
+
+        let mut amount_ticks = 0;
+        while self.accumulator >= self.tick_duration {
+            if amount_ticks == self.max_ticks_per_frame {
+                log::debug!(
+                    "Frame ran {amount_ticks} ticks, dropping {:?} behind",
+                    self.accumulator
+                );
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+
+            self.accumulator -= self.tick_duration;
+            amount_ticks += 1;
+            self.ticks += 1;
+            function(self.time_scale.delta(self.delta));
+        }
+    }
+
+    #[inline]
+    fn render(&mut self, mut function: impl FnMut(f32)) {
+        function(self.alpha());
+    }
+
+    #[inline]
+    fn after_frame(&mut self) {
+        if !self.limit_frame_rate {
+            return;
+        }
+
+        let elapsed = self.clock.now() - self.last_update;
+
+        if let Some(duration) = self.target_frame_time.checked_sub(elapsed) {
+            self.clock.sleep(duration);
+        }
+    }
+
+    fn limit_frame_rate(&mut self, limit: bool) {
+        self.limit_frame_rate = limit;
+    }
+
+    fn set_time_scale(&mut self, scale: f32, paused: bool) {
+        self.time_scale = TimeScale { scale, paused };
+    }
+
+    #[inline]
+    fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    fn count_tick(&mut self) {
+        self.ticks += 1;
+    }
+}
+
+/// Runs exactly one update per frame, with the time since the previous one.
+pub struct VariableScheduler {
+    clock: Box<dyn Clock>,
+    time_scale: TimeScale,
+    last_frame: Duration,
+    target_frame_time: Duration,
+    limit_frame_rate: bool,
+    ticks: u64,
+}
+
+impl VariableScheduler {
+    /// `target_fps` has to be positive.
+    pub fn new(target_fps: u16, clock: Box<dyn Clock>) -> Self {
+        assert!(target_fps > 0, "frame rate has to be positive");
+
+        Self {
+            last_frame: clock.now(),
+            clock,
+            time_scale: TimeScale::default(),
+            target_frame_time: Duration::from_secs_f64(1.0 / target_fps as f64),
+            limit_frame_rate: true,
+            ticks: 0,
+        }
+    }
+}
+
+impl TimestepScheduler for VariableScheduler {
+    #[inline]
+    fn update(&mut self, mut function: impl FnMut(f32)) {
+        let now = self.clock.now();
+        let delta = self.time_scale.simulated(now - self.last_frame);
+        self.last_frame = now;
+
+        self.ticks += 1;
+        function(
+            self.time_scale
+                .delta(delta.as_secs_f32().min(MAX_FRAME_DELTA)),
+        );
+    }
+
+    /// Frames always show the latest update, there is nothing to interpolate.
+    #[inline]
+    fn render(&mut self, mut function: impl FnMut(f32)) {
+        function(1.0);
+    }
+
+    #[inline]
+    fn after_frame(&mut self) {
+        if !self.limit_frame_rate {
+            return;
+        }
+
+        let elapsed = self.clock.now() - self.last_frame;
+
+        if let Some(duration) = self.target_frame_time.checked_sub(elapsed) {
+            self.clock.sleep(duration);
+        }
+    }
+
+    fn limit_frame_rate(&mut self, limit: bool) {
+        self.limit_frame_rate = limit;
+    }
+
+    fn set_time_scale(&mut self, scale: f32, paused: bool) {
+        self.time_scale = TimeScale { scale, paused };
+    }
+
+    #[inline]
+    fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    fn count_tick(&mut self) {
+        self.ticks += 1;
+    }
+}
+
+/// Fixed ticks like [`FixedUpdateScheduler`], plus a per frame update.
+pub struct HybridScheduler {
+    fixed: FixedUpdateScheduler,
+    last_frame: Duration,
+}
+
+impl HybridScheduler {
+    pub fn new(fixed: FixedUpdateScheduler) -> Self {
+        Self {
+            last_frame: fixed.clock.now(),
+            fixed,
+        }
+    }
+}
+
+impl TimestepScheduler for HybridScheduler {
+    #[inline]
+    fn update(&mut self, function: impl FnMut(f32)) {
+        self.fixed.update(function);
+    }
+
+    #[inline]
+    fn frame_update(&mut self, mut function: impl FnMut(f32)) {
+        let now = self.fixed.clock.now();
+        let time_scale = self.fixed.time_scale;
+        let delta = time_scale.simulated(now - self.last_frame);
+        self.last_frame = now;
+
+        function(time_scale.delta(delta.as_secs_f32().min(MAX_FRAME_DELTA)));
+    }
 
-        for x in 0..amount_ticks {
-            if x == amount_ticks - 1 {
-                self.last_tick = Instant::now();
+    #[inline]
+    fn render(&mut self, function: impl FnMut(f32)) {
+        self.fixed.render(function);
+    }
+
+    #[inline]
+    fn after_frame(&mut self) {
+        self.fixed.after_frame();
+    }
+
+    fn limit_frame_rate(&mut self, limit: bool) {
+        self.fixed.limit_frame_rate(limit);
+    }
+
+    fn set_time_scale(&mut self, scale: f32, paused: bool) {
+        self.fixed.set_time_scale(scale, paused);
+    }
+
+    #[inline]
+    fn ticks(&self) -> u64 {
+        self.fixed.ticks()
+    }
+
+    fn count_tick(&mut self) {
+        self.fixed.count_tick();
+    }
+}
+
+/// The scheduler picked by [`TimestepMode`], the trait is not object safe.
+pub enum Scheduler {
+    Variable(VariableScheduler),
+    Fixed(FixedUpdateScheduler),
+    Hybrid(HybridScheduler),
+}
+
+impl Scheduler {
+    pub fn new(
+        mode: TimestepMode,
+        target_tps: u16,
+        target_fps: u16,
+        max_ticks_per_frame: u32,
+        clock: Box<dyn Clock>,
+    ) -> Self {
+        let fixed = |clock| {
+            FixedUpdateScheduler::new(target_tps, target_fps, clock)
+                .with_max_ticks_per_frame(max_ticks_per_frame)
+        };
+
+        match mode {
+            TimestepMode::Variable => {
+                Scheduler::Variable(VariableScheduler::new(target_fps, clock))
             }
-            function(self.delta);
+            TimestepMode::Fixed => Scheduler::Fixed(fixed(clock)),
+            TimestepMode::Hybrid => Scheduler::Hybrid(HybridScheduler::new(fixed(clock))),
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($self:ident, $scheduler:ident => $call:expr) => {
+        match $self {
+            Scheduler::Variable($scheduler) => $call,
+            Scheduler::Fixed($scheduler) => $call,
+            Scheduler::Hybrid($scheduler) => $call,
         }
+    };
+}
+
+impl TimestepScheduler for Scheduler {
+    #[inline]
+    fn update(&mut self, function: impl FnMut(f32)) {
+        delegate!(self, scheduler => scheduler.update(function))
     }
 
     #[inline]
-    fn render(&mut self, mut function: impl FnMut()) {
-        function();
+    fn frame_update(&mut self, function: impl FnMut(f32)) {
+        delegate!(self, scheduler => scheduler.frame_update(function))
+    }
+
+    #[inline]
+    fn render(&mut self, function: impl FnMut(f32)) {
+        delegate!(self, scheduler => scheduler.render(function))
     }
 
     #[inline]
     fn after_frame(&mut self) {
-        if let Some(duration) = self.target_frame_time.checked_sub(self.last_tick.elapsed()) {
-            std::thread::sleep(duration);
+        delegate!(self, scheduler => scheduler.after_frame())
+    }
+
+    fn limit_frame_rate(&mut self, limit: bool) {
+        delegate!(self, scheduler => scheduler.limit_frame_rate(limit))
+    }
+
+    fn set_time_scale(&mut self, scale: f32, paused: bool) {
+        delegate!(self, scheduler => scheduler.set_time_scale(scale, paused))
+    }
+
+    #[inline]
+    fn ticks(&self) -> u64 {
+        delegate!(self, scheduler => scheduler.ticks())
+    }
+
+    fn count_tick(&mut self) {
+        delegate!(self, scheduler => scheduler.count_tick())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn fixed(clock: &ManualClock) -> FixedUpdateScheduler {
+        FixedUpdateScheduler::new(10, 10, Box::new(clock.clone()))
+    }
+
+    /// Deltas of the ticks one update ran.
+    fn update(scheduler: &mut impl TimestepScheduler) -> Vec<f32> {
+        let mut deltas = Vec::new();
+        scheduler.update(|delta| deltas.push(delta));
+        deltas
+    }
+
+    fn alpha(scheduler: &mut impl TimestepScheduler) -> f32 {
+        let mut alpha = f32::NAN;
+        scheduler.render(|value| alpha = value);
+        alpha
+    }
+
+    #[test]
+    fn fixed_runs_a_tick_per_tick_duration() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock);
+
+        assert_eq!(update(&mut scheduler), Vec::<f32>::new());
+
+        clock.advance(TICK);
+        assert_eq!(update(&mut scheduler), vec![0.1]);
+
+        clock.advance(TICK * 3);
+        assert_eq!(update(&mut scheduler), vec![0.1; 3]);
+        assert_eq!(scheduler.ticks(), 4);
+    }
+
+    #[test]
+    fn fixed_carries_the_remainder_over() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock);
+
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(30));
+            assert!(update(&mut scheduler).is_empty());
         }
+
+        clock.advance(Duration::from_millis(30));
+        assert_eq!(update(&mut scheduler).len(), 1);
+        assert!((alpha(&mut scheduler) - 0.2).abs() < 1e-6);
+
+        clock.advance(Duration::from_millis(80));
+        assert_eq!(update(&mut scheduler).len(), 1);
+        assert_eq!(alpha(&mut scheduler), 0.0);
+    }
+
+    #[test]
+    fn fixed_clamps_ticks_per_frame() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock).with_max_ticks_per_frame(3);
+
+        clock.advance(TICK * 10 + Duration::from_millis(50));
+        assert_eq!(update(&mut scheduler).len(), 3);
+
+        // the time beyond the clamp is dropped instead of caught up on
+        assert_eq!(alpha(&mut scheduler), 0.0);
+        assert!(update(&mut scheduler).is_empty());
+    }
+
+    #[test]
+    fn fixed_alpha_is_the_fraction_of_the_next_tick() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock);
+
+        clock.advance(Duration::from_millis(25));
+        update(&mut scheduler);
+        assert_eq!(alpha(&mut scheduler), 0.25);
+
+        clock.advance(Duration::from_millis(125));
+        update(&mut scheduler);
+        assert_eq!(alpha(&mut scheduler), 0.5);
+    }
+
+    #[test]
+    fn fixed_scales_and_pauses_time() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock);
+
+        scheduler.set_time_scale(2.0, false);
+        clock.advance(TICK);
+        assert_eq!(update(&mut scheduler), vec![0.1; 2]);
+
+        // paused ticks keep running so input is handled, without time passing
+        scheduler.set_time_scale(2.0, true);
+        clock.advance(TICK);
+        assert_eq!(update(&mut scheduler), vec![0.0]);
+    }
+
+    #[test]
+    fn fixed_paces_frames_with_the_clock() {
+        let clock = ManualClock::new();
+        let mut scheduler = FixedUpdateScheduler::new(10, 20, Box::new(clock.clone()));
+
+        clock.advance(Duration::from_millis(20));
+        update(&mut scheduler);
+        scheduler.after_frame();
+        assert_eq!(clock.now(), Duration::from_millis(70));
+
+        scheduler.limit_frame_rate(false);
+        update(&mut scheduler);
+        scheduler.after_frame();
+        assert_eq!(clock.now(), Duration::from_millis(70));
+    }
+
+    #[test]
+    fn counted_ticks_do_not_consume_time() {
+        let clock = ManualClock::new();
+        let mut scheduler = fixed(&clock);
+
+        scheduler.count_tick();
+        assert_eq!(scheduler.ticks(), 1);
+
+        clock.advance(TICK);
+        assert_eq!(update(&mut scheduler).len(), 1);
+        assert_eq!(scheduler.ticks(), 2);
+    }
+
+    #[test]
+    fn variable_runs_one_tick_per_update_with_the_elapsed_time() {
+        let clock = ManualClock::new();
+        let mut scheduler = VariableScheduler::new(60, Box::new(clock.clone()));
+
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(update(&mut scheduler), vec![0.04]);
+
+        assert_eq!(update(&mut scheduler), vec![0.0]);
+        assert_eq!(scheduler.ticks(), 2);
+        assert_eq!(alpha(&mut scheduler), 1.0);
+    }
+
+    #[test]
+    fn variable_clamps_long_frames() {
+        let clock = ManualClock::new();
+        let mut scheduler = VariableScheduler::new(60, Box::new(clock.clone()));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(update(&mut scheduler), vec![MAX_FRAME_DELTA]);
+    }
+
+    #[test]
+    fn variable_scales_and_pauses_time() {
+        let clock = ManualClock::new();
+        let mut scheduler = VariableScheduler::new(60, Box::new(clock.clone()));
+
+        scheduler.set_time_scale(0.5, false);
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(update(&mut scheduler), vec![0.02]);
+
+        scheduler.set_time_scale(0.5, true);
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(update(&mut scheduler), vec![0.0]);
+    }
+
+    #[test]
+    fn hybrid_ticks_fixed_and_updates_every_frame() {
+        let clock = ManualClock::new();
+        let mut scheduler = HybridScheduler::new(fixed(&clock));
+
+        let mut frame_deltas = Vec::new();
+
+        clock.advance(Duration::from_millis(150));
+        assert_eq!(update(&mut scheduler), vec![0.1]);
+        scheduler.frame_update(|delta| frame_deltas.push(delta));
+        assert_eq!(alpha(&mut scheduler), 0.5);
+
+        clock.advance(Duration::from_millis(20));
+        assert!(update(&mut scheduler).is_empty());
+        scheduler.frame_update(|delta| frame_deltas.push(delta));
+        assert!((alpha(&mut scheduler) - 0.7).abs() < 1e-6);
+
+        assert_eq!(frame_deltas, vec![0.15, 0.02]);
+        assert_eq!(scheduler.ticks(), 1);
+    }
+
+    #[test]
+    fn hybrid_clamps_long_frames() {
+        let clock = ManualClock::new();
+        let mut scheduler = HybridScheduler::new(fixed(&clock).with_max_ticks_per_frame(2));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(update(&mut scheduler).len(), 2);
+
+        let mut frame_delta = None;
+        scheduler.frame_update(|delta| frame_delta = Some(delta));
+        assert_eq!(frame_delta, Some(MAX_FRAME_DELTA));
     }
 }
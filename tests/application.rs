@@ -0,0 +1,22 @@
+use mush::{application::Application, timestep_scheduler::TimestepMode};
+use pollster::FutureExt;
+
+#[test]
+fn zero_rates_are_rejected() {
+    let build = |builder: mush::application::ApplicationBuilder| {
+        builder
+            .headless(8, 8)
+            .without_rendering()
+            .build()
+            .block_on()
+    };
+
+    assert!(build(Application::builder().tick_rate(0)).is_err());
+    assert!(build(Application::builder().frame_rate(0)).is_err());
+    assert!(build(
+        Application::builder()
+            .timestep(TimestepMode::Variable)
+            .tick_rate(0)
+    )
+    .is_ok());
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use mush::{
+    application::Application,
+    clock::ManualClock,
+    plugins::{
+        actions::{ActionState, Binding, InputMap},
+        gamepad::{
+            Axis, Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType,
+            GamepadEvent, Gamepads, RumbleRequest, ScriptedBackend,
+        },
+        input::Input,
+    },
+};
+use pollster::FutureExt;
+
+const PAD: Gamepad = Gamepad(0);
+const SOUTH: GamepadButton = GamepadButton {
+    gamepad: PAD,
+    button: GamepadButtonType::South,
+};
+const LEFT_X: GamepadAxis = GamepadAxis {
+    gamepad: PAD,
+    axis: GamepadAxisType::LeftStickX,
+};
+
+struct Harness {
+    app: Application,
+    backend: ScriptedBackend,
+    clock: ManualClock,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let backend = ScriptedBackend::new();
+        let clock = ManualClock::new();
+
+        let input_map = InputMap::default()
+            .with_binding("Jump", Binding::GamepadButton(GamepadButtonType::South))
+            .with_binding(
+                "Move",
+                Binding::GamepadAxis {
+                    axis: GamepadAxisType::LeftStickX,
+                    dead_zone: 0.2,
+                },
+            );
+
+        let app = Application::builder()
+            .headless(8, 8)
+            .without_rendering()
+            .input_backend(backend.clone())
+            .input_map(input_map)
+            .tick_rate(10)
+            .clock(clock.clone())
+            .build()
+            .block_on()
+            .expect("application builds");
+
+        Self {
+            app,
+            backend,
+            clock,
+        }
+    }
+
+    /// Runs a frame with exactly one tick, after the backend reported `events`.
+    fn frame(&mut self, events: impl IntoIterator<Item = GamepadEvent>) {
+        self.backend.push_frame(events);
+        self.clock.advance(Duration::from_millis(100));
+        self.app.update();
+    }
+
+    fn actions(&self) -> &ActionState {
+        self.app.world().resource::<ActionState>()
+    }
+}
+
+fn connect() -> GamepadEvent {
+    GamepadEvent::Connected {
+        gamepad: PAD,
+        name: "Scripted".into(),
+    }
+}
+
+#[test]
+fn connecting_registers_the_gamepad() {
+    let mut harness = Harness::new();
+    harness.frame([connect()]);
+
+    let gamepads = harness.app.world().resource::<Gamepads>();
+    assert!(gamepads.contains(PAD));
+    assert_eq!(gamepads.name(PAD), Some("Scripted"));
+    assert_eq!(harness.backend.pending_frames(), 0);
+}
+
+#[test]
+fn buttons_drive_input_and_actions() {
+    let mut harness = Harness::new();
+    harness.frame([
+        connect(),
+        GamepadEvent::Button {
+            button: SOUTH,
+            pressed: true,
+        },
+    ]);
+
+    assert!(harness.actions().just_pressed("Jump"));
+
+    harness.frame([]);
+    assert!(harness
+        .app
+        .world()
+        .resource::<Input<GamepadButton>>()
+        .pressed(SOUTH));
+    assert!(harness.actions().pressed("Jump"));
+    assert!(!harness.actions().just_pressed("Jump"));
+
+    harness.frame([GamepadEvent::Button {
+        button: SOUTH,
+        pressed: false,
+    }]);
+    assert!(harness.actions().just_released("Jump"));
+}
+
+#[test]
+fn tapped_button_is_just_pressed_once() {
+    let mut harness = Harness::new();
+    harness.frame([
+        connect(),
+        GamepadEvent::Button {
+            button: SOUTH,
+            pressed: true,
+        },
+        GamepadEvent::Button {
+            button: SOUTH,
+            pressed: false,
+        },
+    ]);
+
+    assert!(harness.actions().just_pressed("Jump"));
+
+    harness.frame([]);
+    assert!(!harness.actions().pressed("Jump"));
+}
+
+#[test]
+fn axes_apply_the_dead_zone() {
+    let mut harness = Harness::new();
+    harness.frame([
+        connect(),
+        GamepadEvent::Axis {
+            axis: LEFT_X,
+            value: 0.1,
+        },
+    ]);
+
+    assert_eq!(
+        harness
+            .app
+            .world()
+            .resource::<Axis<GamepadAxis>>()
+            .get(LEFT_X),
+        Some(0.1)
+    );
+    assert_eq!(harness.actions().value("Move"), 0.0);
+
+    harness.frame([GamepadEvent::Axis {
+        axis: LEFT_X,
+        value: -0.6,
+    }]);
+    assert!((harness.actions().value("Move") + 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn disconnecting_releases_everything() {
+    let mut harness = Harness::new();
+    harness.frame([
+        connect(),
+        GamepadEvent::Button {
+            button: SOUTH,
+            pressed: true,
+        },
+        GamepadEvent::Axis {
+            axis: LEFT_X,
+            value: 1.0,
+        },
+    ]);
+    harness.frame([GamepadEvent::Disconnected { gamepad: PAD }]);
+
+    let world = harness.app.world();
+    assert!(!world.resource::<Gamepads>().contains(PAD));
+    assert_eq!(world.resource::<Axis<GamepadAxis>>().get(LEFT_X), None);
+    assert!(harness.actions().just_released("Jump"));
+    assert_eq!(harness.actions().value("Move"), 0.0);
+}
+
+#[test]
+fn rumble_is_forwarded_to_the_backend() {
+    let mut harness = Harness::new();
+    harness.frame([connect()]);
+
+    let request = RumbleRequest {
+        gamepad: PAD,
+        strong: 1.0,
+        weak: 0.5,
+        duration: Duration::from_millis(200),
+    };
+    harness.app.world_mut().send_event(request.clone());
+    harness.frame([]);
+
+    assert_eq!(harness.backend.rumble_requests(), vec![request]);
+}
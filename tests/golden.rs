@@ -0,0 +1,32 @@
+use mush::{
+    golden::GoldenTest,
+    plugins::{
+        assets::AssetServer,
+        images::Image,
+        rendering::{Camera, ScalingMode},
+        sprites::Sprite,
+        transform::Transform,
+    },
+};
+
+#[test]
+fn sprite_scene() -> Result<(), anyhow::Error> {
+    GoldenTest::new("sprite_scene").size(128, 128).run(|world| {
+        world.spawn(Camera::orthographic(ScalingMode::FixedVertical(2.0)));
+
+        let happy_tree = world
+            .resource::<AssetServer>()
+            .load::<Image>("happy-tree.png");
+
+        world.spawn((Sprite::new(happy_tree), Transform::from_xyz(-0.4, 0.0, 0.0)));
+
+        world.spawn((
+            Sprite {
+                tint: glam::Vec4::new(1.0, 0.5, 0.5, 1.0),
+                flip_x: true,
+                ..Sprite::new(happy_tree)
+            },
+            Transform::from_xyz(0.5, 0.3, 0.1).with_scale(glam::Vec3::splat(0.5)),
+        ));
+    })
+}
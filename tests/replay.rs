@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use bevy_ecs::{
+    component::Component,
+    query::With,
+    system::{Query, Res, ResMut, Resource},
+};
+use glam::Vec3;
+use mush::{
+    application::{Application, FrameUpdateSchedule, UpdateSchedule},
+    clock::ManualClock,
+    plugins::{
+        actions::{ActionState, Binding, InputMap},
+        input::InputEvent,
+        transform::{set_parent, GlobalTransform, Transform, TransformPlugin},
+        App, Plugin, PluginId,
+    },
+    recording::InputRecording,
+    resources::Time,
+    timestep_scheduler::TimestepMode,
+};
+use pollster::FutureExt;
+use winit::keyboard::KeyCode;
+
+#[derive(Component)]
+struct Player;
+
+#[derive(Component)]
+struct Hand;
+
+/// Where the hand was seen by every tick, which depends on the transforms
+/// propagated by the previous frame.
+#[derive(Resource, Default)]
+struct HandLog(Vec<Vec3>);
+
+/// Sum of the per frame deltas.
+#[derive(Resource, Default)]
+struct FrameTime(f32);
+
+struct Game;
+
+impl Plugin for Game {
+    fn build(self: Box<Self>, app: &mut App) -> Result<(), anyhow::Error> {
+        let world = app.world_mut();
+        world.init_resource::<HandLog>();
+        world.init_resource::<FrameTime>();
+
+        let player = world.spawn((Player, Transform::IDENTITY)).id();
+        let hand = world.spawn((Hand, Transform::from_xyz(0.0, 1.0, 0.0))).id();
+        set_parent(world, hand, player);
+
+        app.add_systems(UpdateSchedule, (move_system, time_control_system))
+            .add_systems(FrameUpdateSchedule, frame_time_system);
+
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Vec<PluginId> {
+        vec![PluginId::of::<TransformPlugin>()]
+    }
+}
+
+fn move_system(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    mut players: Query<&mut Transform, With<Player>>,
+    hands: Query<&GlobalTransform, With<Hand>>,
+    mut log: ResMut<HandLog>,
+) {
+    for mut transform in &mut players {
+        transform.translation.x += actions.value("Move") * 3.0 * time.delta();
+    }
+
+    log.0.extend(hands.iter().map(GlobalTransform::translation));
+}
+
+/// Pauses and slows down in the middle of a frame, the change has to wait for
+/// the next one.
+fn time_control_system(actions: Res<ActionState>, mut time: ResMut<Time>) {
+    if actions.just_pressed("Pause") {
+        match time.is_paused() {
+            true => time.resume(),
+            false => time.pause(),
+        }
+    }
+
+    if actions.just_pressed("Slow") {
+        time.set_scale(0.5);
+    }
+}
+
+fn frame_time_system(time: Res<Time>, mut frame_time: ResMut<FrameTime>) {
+    frame_time.0 += time.delta();
+}
+
+#[derive(PartialEq, Debug)]
+struct Snapshot {
+    player: Transform,
+    /// Only there once transforms were propagated
+    hand: Option<GlobalTransform>,
+    hand_log: Vec<Vec3>,
+    frame_time: f32,
+    elapsed: f64,
+    ticks: u64,
+    time_scale: f32,
+    paused: bool,
+}
+
+fn snapshot(app: &mut Application) -> Snapshot {
+    let world = app.world_mut();
+
+    let player = *world
+        .query_filtered::<&Transform, With<Player>>()
+        .single(world);
+    let hand = world
+        .query_filtered::<&GlobalTransform, With<Hand>>()
+        .get_single(world)
+        .ok()
+        .copied();
+    let time = world.resource::<Time>();
+
+    Snapshot {
+        player,
+        hand,
+        hand_log: world.resource::<HandLog>().0.clone(),
+        frame_time: world.resource::<FrameTime>().0,
+        elapsed: time.elapsed(),
+        ticks: time.ticks(),
+        time_scale: time.scale(),
+        paused: time.is_paused(),
+    }
+}
+
+fn build(mode: TimestepMode, clock: &ManualClock, record: Option<&str>) -> Application {
+    let input_map = InputMap::default()
+        .with_binding(
+            "Move",
+            Binding::KeyAxis {
+                negative: KeyCode::KeyA,
+                positive: KeyCode::KeyD,
+            },
+        )
+        .with_binding("Pause", Binding::Key(KeyCode::KeyP))
+        .with_binding("Slow", Binding::Key(KeyCode::KeyS));
+
+    let mut builder = Application::builder()
+        .headless(8, 8)
+        .without_rendering()
+        .input_map(input_map)
+        .timestep(mode)
+        .tick_rate(10)
+        .max_ticks_per_frame(3)
+        .clock(clock.clone())
+        .add_plugin(Game);
+
+    if let Some(path) = record {
+        builder = builder.record_input(path);
+    }
+
+    builder.build().block_on().expect("application builds")
+}
+
+fn key(key: KeyCode, pressed: bool) -> InputEvent {
+    InputEvent::Key { key, pressed }
+}
+
+/// Frames of uneven length, some without ticks and some clamped, with input
+/// arriving between them.
+fn play(app: &mut Application, clock: &ManualClock) {
+    let frames: [(u64, &[InputEvent]); 12] = [
+        (16, &[key(KeyCode::KeyD, true)]),
+        (120, &[]),
+        (40, &[]),
+        (260, &[key(KeyCode::KeyP, true)]),
+        (70, &[key(KeyCode::KeyP, false)]),
+        (150, &[key(KeyCode::KeyD, false), key(KeyCode::KeyA, true)]),
+        (30, &[key(KeyCode::KeyP, true), key(KeyCode::KeyP, false)]),
+        (500, &[key(KeyCode::KeyS, true)]),
+        (90, &[]),
+        (7, &[key(KeyCode::KeyS, false)]),
+        (210, &[]),
+        (45, &[key(KeyCode::KeyA, false)]),
+    ];
+
+    for (millis, events) in frames {
+        for event in events {
+            app.send_input(event.clone());
+        }
+
+        clock.advance(Duration::from_millis(millis));
+        app.update();
+    }
+}
+
+fn record_and_replay(mode: TimestepMode) {
+    let path = std::env::temp_dir().join(format!("mush-replay-{mode:?}.txt"));
+    let path = path.to_str().unwrap();
+
+    let clock = ManualClock::new();
+    let mut app = build(mode, &clock, Some(path));
+    play(&mut app, &clock);
+    let recorded = snapshot(&mut app);
+    // saves the recording
+    drop(app);
+
+    let recording = InputRecording::load(path).expect("recording loads");
+    std::fs::remove_file(path).unwrap();
+
+    // the clock never moves, everything comes from the recording
+    let mut app = build(mode, &ManualClock::new(), None);
+    app.replay(&recording);
+
+    assert_eq!(snapshot(&mut app), recorded);
+    assert_eq!(recorded.time_scale, 0.5);
+    assert!(recorded.player.translation.x != 0.0);
+}
+
+#[test]
+fn fixed_replay_matches_the_recorded_run() {
+    record_and_replay(TimestepMode::Fixed);
+}
+
+#[test]
+fn variable_replay_matches_the_recorded_run() {
+    record_and_replay(TimestepMode::Variable);
+}
+
+#[test]
+fn hybrid_replay_matches_the_recorded_run() {
+    record_and_replay(TimestepMode::Hybrid);
+}